@@ -0,0 +1,158 @@
+use std::{mem, sync::Arc, time::{Duration, Instant}};
+use parking_lot::Mutex;
+use crate::{CallbackHint, Result, Timer, TimerQueue, common::TimerArm};
+
+// ------------------------------------- DATA STRUCTURES & MARKERS ------------------------------------
+/// Collects items from many threads, and hands them to a flush callback every interval, or as soon as a batch is full,
+/// whichever comes first. A full batch is flushed alone, so batches never exceed the maximum size, and the remaining items
+/// are flushed when the batcher is closed or dropped.
+///
+/// Flushes run from the timer queue, one at a time; empty batches are not flushed.
+///
+/// # Examples
+///
+/// ```
+/// # use std::{sync::{Arc, Mutex}, thread, time::Duration};
+/// use native_timer::{Batcher, TimerQueue};
+///
+/// let batches = Arc::new(Mutex::new(Vec::new()));
+/// let sink = batches.clone();
+/// let batcher = Batcher::new(TimerQueue::default(), Duration::from_millis(500), 3, None, move |batch: Vec<i32>| {
+///     sink.lock().unwrap().push(batch);
+/// }).unwrap();
+///
+/// for n in 1..=4 {
+///     batcher.push(n).unwrap();
+/// }
+/// thread::sleep(Duration::from_millis(100));
+/// assert_eq!(*batches.lock().unwrap(), [vec![1, 2, 3]]);
+///
+/// batcher.close().unwrap();
+/// assert_eq!(*batches.lock().unwrap(), [vec![1, 2, 3], vec![4]]);
+/// ```
+pub struct Batcher<'h, T> {
+    shared: Arc<BatchShared<'h, T>>,
+    max_items: usize,
+    timer: Option<Timer<'h>>
+}
+
+type FlushFn<'h, T> = Box<dyn FnMut(Vec<T>) + Send + 'h>;
+
+struct BatchShared<'h, T> {
+    items: Mutex<Batch<T>>,
+    flush: Mutex<FlushFn<'h, T>>
+}
+
+struct Batch<T> {
+    items: Vec<T>,
+    /// Full batches waiting for the flush.
+    full: Vec<Vec<T>>,
+    next_flush: Instant,
+    arm: Option<TimerArm>
+}
+
+// --------------------------------------- IMPLEMENTATIONS --------------------------------------------
+impl<'h, T: Send + 'h> Batcher<'h, T> {
+    /// Create a batcher on `queue`, which calls `flush` every `interval`, or when `max_items` items are collected.
+    pub fn new<F>(queue: &TimerQueue, interval: Duration, max_items: usize, hint: Option<CallbackHint>, flush: F) -> Result<Self>
+        where F: FnMut(Vec<T>) + Send + 'h
+    {
+        let shared = Arc::new(BatchShared {
+            items: Mutex::new(Batch { items: Vec::new(), full: Vec::new(), next_flush: Instant::now() + interval, arm: None }),
+            flush: Mutex::new(Box::new(flush) as FlushFn<'h, T>)
+        });
+        let batch = shared.clone();
+        let (timer, arm) = queue.schedule_manual(hint, move |_| {
+            let batches = batch.items.lock().fire(Instant::now(), interval);
+            batches.into_iter().for_each(|items| batch.flush(items));
+        })?;
+        {
+            let mut b = shared.items.lock();
+            b.arm = Some(arm);
+            b.arm(interval);
+        }
+        Ok(Batcher { shared, max_items, timer: Some(timer) })
+    }
+
+    /// Add an item to the current batch. A full batch is flushed right away.
+    pub fn push(&self, item: T) -> Result<()> {
+        let mut b = self.shared.items.lock();
+        b.items.push(item);
+        if b.items.len() >= self.max_items {
+            let items = mem::take(&mut b.items);
+            b.full.push(items);
+            b.arm.as_ref().map_or(Ok(()), |arm| arm.arm(Duration::ZERO))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Number of items waiting for the next flush.
+    pub fn len(&self) -> usize {
+        let b = self.shared.items.lock();
+        b.items.len() + b.full.iter().map(Vec::len).sum::<usize>()
+    }
+
+    /// Check whether no item is waiting for the next flush.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'h, T> Batcher<'h, T> {
+    /// Stop the timer, and flush the remaining items in the current thread.
+    pub fn close(mut self) -> Result<()> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        if let Some(mut timer) = self.timer.take() {
+            timer.close()?;
+            let batches = self.shared.items.lock().take_all();
+            batches.into_iter().for_each(|items| self.shared.flush(items));
+        }
+        Ok(())
+    }
+}
+
+impl<'h, T> BatchShared<'h, T> {
+    fn flush(&self, items: Vec<T>) {
+        if !items.is_empty() {
+            (self.flush.lock())(items);
+        }
+    }
+}
+
+impl<T> Batch<T> {
+    /// Handle a tick of the timer, and return the batches to flush. The current batch is only flushed when its interval
+    /// has passed, otherwise the tick is for full batches.
+    fn fire(&mut self, now: Instant, interval: Duration) -> Vec<Vec<T>> {
+        let mut batches = mem::take(&mut self.full);
+        if now >= self.next_flush {
+            batches.push(mem::take(&mut self.items));
+            self.next_flush = now + interval;
+        }
+        self.arm(self.next_flush - now);
+        batches
+    }
+
+    fn take_all(&mut self) -> Vec<Vec<T>> {
+        let mut batches = mem::take(&mut self.full);
+        batches.push(mem::take(&mut self.items));
+        batches
+    }
+
+    fn arm(&self, due: Duration) {
+        if let Some(arm) = &self.arm {
+            arm.arm_or_warn(due, "batcher");
+        }
+    }
+}
+
+impl<'h, T> Drop for Batcher<'h, T> {
+    fn drop(&mut self) {
+        if let Err(e) = self.shutdown() {
+            println!("WARNING: an error occurred while closing the batcher. Error = {e:?}");
+        }
+    }
+}
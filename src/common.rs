@@ -1,7 +1,8 @@
-use std::{ sync, sync::atomic, time::Duration, sync::atomic::Ordering };
-use sync_wait_object::{WaitEvent};
+use std::{ any::Any, error::Error, panic, sync, sync::atomic, time::{Duration, Instant}, sync::atomic::Ordering, sync::mpsc, thread };
+use parking_lot::{Mutex, RwLock};
+use sync_wait_object::{WaitEvent, WaitObjectError};
 use crate::{
-    Result, platform, CallbackHint
+    Result, platform, CallbackHint, TimerError, TickInfo, TickControl, TimerFailure, TimerStatus, PanicPolicy, RepeatLimit
 };
 
 use platform::{TimerQueue, TimerQueueCore};
@@ -9,8 +10,8 @@ use platform::{TimerQueue, TimerQueueCore};
 // ------------------------------------- DATA STRUCTURES & MARKERS ------------------------------------
 enum FType<'h> {
     None,
-    Mut(Box<dyn FnMut() + 'h>),
-    Once(Box<dyn FnOnce() + 'h>)
+    Mut(Box<dyn FnMut(&TickInfo) -> TickResult + 'h>),
+    Once(Box<dyn FnOnce(&TickInfo) + 'h>)
 }
 unsafe impl<'h> Send for FType<'h> {}
 unsafe impl<'h> Sync for FType<'h> {}
@@ -19,125 +20,624 @@ unsafe impl<'h> Sync for FType<'h> {}
 pub(crate) struct MutWrapper<'h> {
     pub hint: Option<CallbackHint>,
 
+    id: CallbackId,
+    ticks: atomic::AtomicU64,
     idle: IdleWaitType,
     mark_deleted: atomic::AtomicBool,
+    status: Mutex<TimerStatus>,
+    failures: atomic::AtomicU32,
+    failure_limit: Option<u32>,
+    panic: Mutex<Option<PanicPayload>>,
+    cadence: Cadence,
+    limit: Option<RepeatLimit>,
+    last_tick: WaitEvent<Option<TickInfo>>,
+    completed: WaitEvent<Option<TickInfo>>,
+    native: atomic::AtomicUsize,
+    period: Mutex<Duration>,
     main_queue: sync::Arc<TimerQueueCore>,
     f: FType<'h>
 }
 
 pub(crate) trait MutCallable {
     fn call(&mut self) -> Result<()>;
-    fn wait_idle(&self, acceptable_execution_time: Duration) -> Result<()>;
+    fn wait_idle(&self, acceptable_execution_time: Option<Duration>) -> Result<()>;
 }
 
 pub(crate) type MutWrapperUnsafeRepr = usize;
+pub(crate) type TimerHandleUnsafeRepr = usize;
+
+/// An identity of a registered [`MutWrapper`]. This value, instead of the wrapper's address, is given to the OS timer so
+/// a late notification of a destroyed timer can be detected and ignored.
+pub(crate) type CallbackId = usize;
 
 type IdleWaitType = WaitEvent<i32>;
-struct CriticalSection<'a>(&'a mut IdleWaitType);
+
+/// State owned by a timer, which the timer's closure can access on every tick. Timers without a state own `()`.
+pub(crate) type TimerState<S> = sync::Arc<Mutex<Option<S>>>;
+
+pub(crate) type BoxedError = Box<dyn Error + Send + Sync>;
+
+/// Outcome of a handler's execution.
+pub(crate) type TickResult = std::result::Result<TickControl, BoxedError>;
+
+pub(crate) type ErrorSink = dyn Fn(&TimerFailure) + Send + Sync;
+
+pub(crate) type PanicPayload = Box<dyn Any + Send>;
+
+/// Settings of a timer queue, which are shared by all timers of the queue.
+#[derive(Default)]
+pub(crate) struct QueueSettings {
+    error_sink: RwLock<Option<sync::Arc<ErrorSink>>>,
+    panic_policy: Mutex<PanicPolicy>
+}
+
+/// How the native timer is re-armed.
+#[derive(Copy, Clone, PartialEq)]
+enum Cadence {
+    /// The native timer ticks periodically by itself.
+    FixedRate,
+
+    /// The native timer is re-armed for one period after each call.
+    FixedDelay,
+
+    /// The native timer ticks only when it is armed by its owner, see [`TimerArm`].
+    Manual
+}
+
+/// Arms a manual timer for a single tick, from any thread including the timer's own handler. The owner must not use the
+/// arm after the timer is closed.
+#[derive(Clone)]
+pub(crate) struct TimerArm {
+    core: sync::Arc<TimerQueueCore>,
+    handle: TimerHandleUnsafeRepr
+}
+
+/// Keeps a manual timer armed to the earliest of the deadlines given to it, so one timer can serve many deadlines. The
+/// [`TimerArm`] is attached after the timer is created, since the timer's handler already owns the state holding this.
+#[derive(Default)]
+pub(crate) struct DeadlineArm {
+    arm: Option<TimerArm>,
+    armed_at: Option<Instant>
+}
+
+/// A job of cleaning up timer resources, which may block until the timer's callback finishes.
+pub(crate) type ReaperJob = Box<dyn FnOnce() + Send>;
+
+/// A background thread, owned by a timer queue, for destroying timers without blocking the caller.
+pub(crate) struct Reaper(mpsc::Sender<ReaperJob>);
+
+struct CriticalSection(IdleWaitType);
 
 // ------------------------------------------ FUNCTIONS -----------------------------------------------
-#[cfg(tracker)]
-pub(crate) use with_tracker::*;
+#[cfg(feature = "tracker")]
+use with_tracker::*;
 
-#[cfg(tracker)]
+/// With the tracker, wrappers are kept in a slab of generation-tagged slots. A callback ID is the slot's index combined with
+/// the slot's generation, which changes whenever the slot is released. So an ID of a destroyed wrapper never matches again.
+#[cfg(feature = "tracker")]
 mod with_tracker {
-    use std::{collections::HashSet, sync};
-    use parking_lot::RwLock;
-    use super::MutWrapperUnsafeRepr;
-
-    static TRACKER_INIT: sync::Once = sync::Once::new();
-    static mut TRACKER: Option<RwLock<HashSet<MutWrapperUnsafeRepr>>> = None;
-
-    pub(crate) fn is_mutwrapper_unsafe_repr_valid(key: MutWrapperUnsafeRepr) -> bool { tracker().read().get(&key).is_some() }
-    pub(crate) fn save_mutwrapper_unsafe_repr(key: MutWrapperUnsafeRepr) { tracker().write().insert(key); }
-    pub(crate) fn remove_mutwrapper_unsafe_repr(key: MutWrapperUnsafeRepr) { tracker().write().remove(&key); }
-
-    fn tracker() -> &'static RwLock<HashSet<MutWrapperUnsafeRepr>> {
-        unsafe {
-            TRACKER_INIT.call_once(|| {
-                TRACKER = Some(RwLock::new(HashSet::new()));
-            });
-            TRACKER.as_ref().unwrap()
+    use parking_lot::{RwLock, RwLockReadGuard};
+    use super::{CallbackId, MutWrapperUnsafeRepr};
+
+    const GENERATION_BITS: u32 = usize::BITS / 2;
+    const GENERATION_MASK: usize = (1 << GENERATION_BITS) - 1;
+
+    struct Slot {
+        generation: usize,
+        wrapper: Option<MutWrapperUnsafeRepr>
+    }
+
+    pub(crate) struct Slots {
+        slots: Vec<Slot>,
+        free: Vec<usize>
+    }
+
+    static TRACKER: RwLock<Slots> = RwLock::new(Slots { slots: Vec::new(), free: Vec::new() });
+
+    impl Slots {
+        #[inline]
+        pub fn get(&self, id: CallbackId) -> Option<MutWrapperUnsafeRepr> {
+            let (index, generation) = (id >> GENERATION_BITS, id & GENERATION_MASK);
+            self.slots.get(index).filter(|s| s.generation == generation).and_then(|s| s.wrapper)
+        }
+    }
+
+    pub(crate) fn register(wrapper: MutWrapperUnsafeRepr) -> CallbackId {
+        let mut tracker = TRACKER.write();
+        let index = match tracker.free.pop() {
+            Some(index) => index,
+            None => {
+                tracker.slots.push(Slot { generation: 0, wrapper: None });
+                tracker.slots.len() - 1
+            }
+        };
+        let slot = &mut tracker.slots[index];
+        slot.wrapper = Some(wrapper);
+        (index << GENERATION_BITS) | slot.generation
+    }
+
+    pub(crate) fn unregister(id: CallbackId) {
+        let mut tracker = TRACKER.write();
+        let index = id >> GENERATION_BITS;
+        if tracker.get(id).is_some() {
+            let slot = &mut tracker.slots[index];
+            slot.wrapper = None;
+            slot.generation = (slot.generation + 1) & GENERATION_MASK;
+            tracker.free.push(index);
         }
     }
+
+    #[inline]
+    pub(crate) fn slots() -> RwLockReadGuard<'static, Slots> { TRACKER.read() }
 }
 
-#[cfg(not(tracker))]
-pub(crate) use without_tracker::*;
+#[cfg(not(feature = "tracker"))]
+use without_tracker::*;
 
-#[cfg(not(tracker))]
+/// Without the tracker, a callback ID is simply the wrapper's address.
+#[cfg(not(feature = "tracker"))]
 mod without_tracker {
-    use super::MutWrapperUnsafeRepr;
+    use super::{CallbackId, MutWrapperUnsafeRepr};
+
+    pub(crate) struct Slots;
+
+    impl Slots {
+        #[inline] pub fn get(&self, id: CallbackId) -> Option<MutWrapperUnsafeRepr> { Some(id) }
+    }
+
+    #[inline] pub(crate) fn register(wrapper: MutWrapperUnsafeRepr) -> CallbackId { wrapper }
+    #[inline] pub(crate) fn unregister(_id: CallbackId) { }
+    #[inline] pub(crate) fn slots() -> Slots { Slots }
+}
+
+/// Extract the message of a panic payload, which is usually either `&str` or `String`.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() { s.to_string() }
+    else if let Some(s) = payload.downcast_ref::<String>() { s.clone() }
+    else { "unknown panic payload".to_string() }
+}
+
+#[inline]
+pub(crate) fn timer_state<S>(state: S) -> TimerState<S> {
+    sync::Arc::new(Mutex::new(Some(state)))
+}
+
+/// Call the wrapper registered as `id`. The call is ignored if the wrapper has been marked for deletion.
+pub(crate) fn invoke_callback(id: CallbackId) {
+    let entered = {
+        let slots = slots();
+        slots.get(id).map(|ptr| {
+            let wrapper = unsafe { &mut *(ptr as *mut MutWrapper) };
+            // enter the critical section before releasing the slots, so the timer's closing will wait for this call.
+            let section = CriticalSection::start(&wrapper.idle);
+            (wrapper, section)
+        })
+    };
+    if let Some((wrapper, section)) = entered {
+        if let Err(e) = wrapper.call() {
+            println!("WARNING: Error occurred during timer callback: {e:?}");
+        }
+        drop(section);
+    }
+}
 
-    pub(crate) fn is_mutwrapper_unsafe_repr_valid(_key: MutWrapperUnsafeRepr) -> bool { true }
-    pub(crate) fn save_mutwrapper_unsafe_repr(_key: MutWrapperUnsafeRepr) { }
-    pub(crate) fn remove_mutwrapper_unsafe_repr(_key: MutWrapperUnsafeRepr) { }
+/// Access the wrapper registered as `id`, if it is still alive.
+#[cfg(unix)]
+pub(crate) fn peek_callback<R>(id: CallbackId, f: impl FnOnce(&MutWrapper) -> R) -> Option<R> {
+    let slots = slots();
+    slots.get(id).map(|ptr| f(unsafe { &*(ptr as *const MutWrapper) }))
 }
 
 // --------------------------------------- IMPLEMENTATIONS --------------------------------------------
 impl<'h> MutWrapper<'h> {
-    pub fn new<F>(main_queue: sync::Arc<TimerQueueCore>, hint: Option<CallbackHint>, handler: F) -> Self where F: FnMut() + Send + 'h {
-        MutWrapper::<'h> {
+    #[inline]
+    pub fn new<F>(main_queue: sync::Arc<TimerQueueCore>, hint: Option<CallbackHint>, mut handler: F) -> Box<Self> where F: FnMut() + Send + 'h {
+        Self::new_controlled(main_queue, hint, move |_| { handler(); TickControl::Continue })
+    }
+    #[inline]
+    pub fn new_fallible<F>(main_queue: sync::Arc<TimerQueueCore>, hint: Option<CallbackHint>, failure_limit: Option<u32>, handler: F) -> Box<Self>
+        where F: FnMut(&TickInfo) -> TickResult + Send + 'h
+    {
+        let mut wrapper = Self::register(main_queue, hint, FType::Mut(Box::new(handler)));
+        wrapper.failure_limit = failure_limit;
+        wrapper
+    }
+    #[inline]
+    pub fn new_fixed_delay<F>(main_queue: sync::Arc<TimerQueueCore>, hint: Option<CallbackHint>, handler: F) -> Box<Self> where F: FnMut() + Send + 'h {
+        let mut wrapper = Self::new(main_queue, hint, handler);
+        wrapper.cadence = Cadence::FixedDelay;
+        wrapper
+    }
+    /// A timer which is armed for one tick at a time, where the handler decides the next tick.
+    #[inline]
+    pub fn new_scheduled<F>(main_queue: sync::Arc<TimerQueueCore>, hint: Option<CallbackHint>, handler: F) -> Box<Self>
+        where F: FnMut(&TickInfo) -> TickControl + Send + 'h
+    {
+        let mut wrapper = Self::new_controlled(main_queue, hint, handler);
+        wrapper.cadence = Cadence::FixedDelay;
+        wrapper
+    }
+    /// A timer which ticks once per arming by its [`TimerArm`], see [`MutWrapper::arm`].
+    #[inline]
+    pub fn new_manual<F>(main_queue: sync::Arc<TimerQueueCore>, hint: Option<CallbackHint>, handler: F) -> Box<Self> where F: FnMut(&TickInfo) + Send + 'h {
+        let mut wrapper = Self::new_ticked(main_queue, hint, handler);
+        wrapper.cadence = Cadence::Manual;
+        wrapper
+    }
+    #[inline]
+    pub fn new_repeating<F>(main_queue: sync::Arc<TimerQueueCore>, hint: Option<CallbackHint>, limit: RepeatLimit, handler: F) -> Box<Self> where F: FnMut() + Send + 'h {
+        let mut wrapper = Self::new(main_queue, hint, handler);
+        wrapper.limit = Some(limit);
+        wrapper
+    }
+    #[inline]
+    pub fn new_once<F>(main_queue: sync::Arc<TimerQueueCore>, hints: Option<CallbackHint>, handler: F) -> Box<Self> where F: FnOnce() + Send + 'h {
+        Self::register(main_queue, hints, FType::Once(Box::new(move |_: &TickInfo| handler())))
+    }
+    #[inline]
+    pub fn new_ticked<F>(main_queue: sync::Arc<TimerQueueCore>, hint: Option<CallbackHint>, mut handler: F) -> Box<Self> where F: FnMut(&TickInfo) + Send + 'h {
+        Self::new_controlled(main_queue, hint, move |tick| { handler(tick); TickControl::Continue })
+    }
+    #[inline]
+    pub fn new_controlled<F>(main_queue: sync::Arc<TimerQueueCore>, hint: Option<CallbackHint>, mut handler: F) -> Box<Self>
+        where F: FnMut(&TickInfo) -> TickControl + Send + 'h
+    {
+        Self::register(main_queue, hint, FType::Mut(Box::new(move |tick| Ok(handler(tick)))))
+    }
+    #[allow(dead_code)]
+    pub fn timer_queue(&self) -> TimerQueue {
+        TimerQueue::new_with_context(self.main_queue.clone())
+    }
+    #[inline]
+    pub fn queue_core(&self) -> sync::Arc<TimerQueueCore> {
+        self.main_queue.clone()
+    }
+    /// ID to be given to the OS timer, see [`invoke_callback`].
+    #[inline]
+    pub fn id(&self) -> CallbackId {
+        self.id
+    }
+    /// Bind the wrapper to its native timer, which must be done before the timer is armed.
+    pub(crate) fn attach(&self, handle: TimerHandleUnsafeRepr, period: Duration) {
+        self.native.store(handle, Ordering::SeqCst);
+        *self.period.lock() = period;
+    }
+    /// Period to arm the native timer with. A fixed-delay timer is armed for one tick at a time, and re-armed after each call.
+    #[inline]
+    pub(crate) fn native_period(&self, period: Duration) -> Duration {
+        if self.cadence == Cadence::FixedRate { period } else { platform::ONESHOT_PERIOD }
+    }
+    /// Update the period, when the timer is changed by its owner. This also resumes a stopped or disabled timer.
+    pub(crate) fn reset_period(&self, period: Duration) {
+        *self.period.lock() = period;
+        let mut status = self.status.lock();
+        if *status != TimerStatus::Closed {
+            *status = TimerStatus::Active;
+            self.failures.store(0, Ordering::SeqCst);
+        }
+    }
+    /// Get the arming handle of the native timer, which must have been attached.
+    #[inline]
+    pub(crate) fn arm(&self) -> TimerArm {
+        TimerArm { core: self.main_queue.clone(), handle: self.native.load(Ordering::SeqCst) }
+    }
+    #[inline]
+    pub fn status(&self) -> TimerStatus {
+        *self.status.lock()
+    }
+    /// Wait until the handler finishes a tick after this call, and return the tick.
+    pub fn wait_next_tick(&self, timeout: Option<Duration>) -> Result<TickInfo> {
+        let seen = self.last_tick.value()?.map_or(0, |tick| tick.count);
+        wait_tick(&self.last_tick, timeout, |tick| tick.count > seen)
+    }
+
+    /// Wait until the timer reaches its repeat limit, and return its last tick.
+    #[inline]
+    pub fn wait_completed(&self, timeout: Option<Duration>) -> Result<TickInfo> {
+        wait_tick(&self.completed, timeout, |_| true)
+    }
+    #[inline]
+    pub fn take_panic(&self) -> Option<PanicPayload> {
+        self.panic.lock().take()
+    }
+    pub(crate) fn mark_delete(&self) {
+        self.mark_deleted.store(true, Ordering::SeqCst);
+        *self.status.lock() = TimerStatus::Closed;
+        unregister(self.id);
+    }
+
+    fn register(main_queue: sync::Arc<TimerQueueCore>, hint: Option<CallbackHint>, f: FType<'h>) -> Box<Self> {
+        let mut wrapper = Box::new(MutWrapper::<'h> {
             hint,
+            id: 0,
+            ticks: atomic::AtomicU64::new(0),
             idle: IdleWaitType::new_init(0),
             mark_deleted: atomic::AtomicBool::new(false),
+            status: Mutex::new(TimerStatus::Active),
+            failures: atomic::AtomicU32::new(0),
+            failure_limit: None,
+            panic: Mutex::new(None),
+            cadence: Cadence::FixedRate,
+            limit: None,
+            last_tick: WaitEvent::new_init(None),
+            completed: WaitEvent::new_init(None),
+            native: atomic::AtomicUsize::new(0),
+            period: Mutex::new(Duration::ZERO),
             main_queue,
-            f: FType::Mut(Box::new(handler))
+            f
+        });
+        wrapper.id = register(wrapper.as_ref() as *const MutWrapper as MutWrapperUnsafeRepr);
+        wrapper
+    }
+
+    /// Apply the handler's decision to the native timer.
+    fn apply(&self, control: TickControl) -> Result<()> {
+        if self.mark_deleted.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let handle = self.native.load(Ordering::SeqCst);
+        match control {
+            TickControl::Continue if self.cadence == Cadence::FixedDelay => {
+                let period = *self.period.lock();
+                platform::rearm_timer(&self.main_queue, handle, period, platform::ONESHOT_PERIOD)
+            },
+            TickControl::Continue => Ok(()),
+            TickControl::Stop => self.deactivate(TimerStatus::Stopped),
+            TickControl::Complete => self.complete(),
+            TickControl::RescheduleIn(due) => {
+                let period = *self.period.lock();
+                platform::rearm_timer(&self.main_queue, handle, due, self.native_period(period))
+            },
+            TickControl::ChangePeriod(period) => {
+                *self.period.lock() = period;
+                platform::rearm_timer(&self.main_queue, handle, period, self.native_period(period))
+            }
         }
     }
-    pub fn new_once<F>(main_queue: sync::Arc<TimerQueueCore>, hints: Option<CallbackHint>, handler: F) -> Self where F: FnOnce() + Send + 'h {
-        MutWrapper::<'h> {
-            hint: hints,
-            idle: IdleWaitType::new_init(0),
-            mark_deleted: atomic::AtomicBool::new(false),
-            main_queue,
-            f: FType::Once(Box::new(handler))
+
+    /// Count a failure of the handler, report it to the queue's error sink, and disable the timer when the failure limit
+    /// is reached.
+    fn fail(&self, tick: &TickInfo, error: BoxedError) -> Result<()> {
+        let failures = self.failures.fetch_add(1, Ordering::SeqCst) + 1;
+        let disabled = self.failure_limit.is_some_and(|limit| failures >= limit);
+        let result = if disabled { self.deactivate(TimerStatus::Disabled { failures }) } else { self.apply(TickControl::Continue) };
+
+        self.main_queue.settings.report(&TimerFailure { error: error.as_ref(), tick: *tick, consecutive_failures: failures, disabled });
+        result
+    }
+
+    /// Whether the tick is beyond the repeat limit, so the handler must not be called.
+    fn is_exhausted(&self, tick: &TickInfo) -> bool {
+        match self.limit {
+            Some(RepeatLimit::Times(n)) => tick.count > n,
+            Some(RepeatLimit::Until(deadline)) => tick.time >= deadline,
+            None => false
         }
     }
-    #[allow(dead_code)]
-    pub fn timer_queue(&self) -> TimerQueue {
-        TimerQueue::new_with_context(self.main_queue.clone())
+
+    /// Whether the next tick would be beyond the repeat limit, so the timer can complete right after this tick.
+    fn is_last(&self, tick: &TickInfo) -> bool {
+        match self.limit {
+            Some(RepeatLimit::Times(n)) => tick.count >= n,
+            Some(RepeatLimit::Until(deadline)) => Instant::now() + *self.period.lock() > deadline,
+            None => false
+        }
     }
-    pub(crate) fn mark_delete(&self) {
-        self.mark_deleted.store(true, Ordering::SeqCst);
+
+    fn complete(&self) -> Result<()> {
+        let result = self.deactivate(TimerStatus::Completed);
+        self.signal_completed()?;
+        result
+    }
+
+    /// Complete the timer before its native timer is armed, so the handler is never called.
+    pub(crate) fn complete_unarmed(&self) -> Result<()> {
+        *self.status.lock() = TimerStatus::Completed;
+        self.signal_completed()
+    }
+
+    /// Wake the waiters of the completion with the last tick, or a tick of count 0 if the handler was never called.
+    fn signal_completed(&self) -> Result<()> {
+        let last = *self.last_tick.value()?;
+        let tick = last.unwrap_or(TickInfo { count: 0, time: Instant::now() });
+        self.completed.clone().set_state(Some(tick)).map_err(|e| e.into())
+    }
+
+    /// Handle a panic of the handler according to the queue's panic policy. The payload is kept for the timer's owner.
+    fn recover(&self, payload: PanicPayload) -> Result<()> {
+        let policy = self.main_queue.settings.panic_policy();
+        let message = panic_message(payload.as_ref());
+        *self.panic.lock() = Some(payload);
+        match policy {
+            PanicPolicy::LogAndContinue => {
+                println!("WARNING: timer handler panicked: {message}");
+                self.apply(TickControl::Continue)
+            },
+            PanicPolicy::DisableTimer => {
+                println!("WARNING: timer handler panicked, the timer is disabled: {message}");
+                self.deactivate(TimerStatus::Panicked)
+            },
+            PanicPolicy::Abort => {
+                println!("ERROR: timer handler panicked, aborting: {message}");
+                std::process::abort()
+            }
+        }
+    }
+
+    fn deactivate(&self, new_status: TimerStatus) -> Result<()> {
+        {
+            let mut status = self.status.lock();
+            if *status == TimerStatus::Closed {
+                return Ok(());
+            }
+            *status = new_status;
+        }
+        platform::disarm_timer(&self.main_queue, self.native.load(Ordering::SeqCst))
     }
 }
 
 impl<'h> MutCallable for MutWrapper<'h> {
     fn call(&mut self) -> Result<()> {
-        let section = CriticalSection::start(&mut self.idle);
-        let is_deleted = self.mark_deleted.load(Ordering::SeqCst);
-        if !is_deleted {
-            match &mut self.f {
+        let section = CriticalSection::start(&self.idle);
+        let is_active = !self.mark_deleted.load(Ordering::SeqCst) && self.status() == TimerStatus::Active;
+        let mut result = Ok(());
+        if is_active {
+            let tick = TickInfo {
+                count: self.ticks.fetch_add(1, Ordering::SeqCst) + 1,
+                time: Instant::now()
+            };
+            if self.is_exhausted(&tick) {
+                let result = self.complete();
+                drop(section);
+                return result;
+            }
+            let f = &mut self.f;
+            // a panic must not unwind into the OS timer thread or the queue's dispatcher.
+            let outcome = panic::catch_unwind(panic::AssertUnwindSafe(|| match f {
                 FType::Once(_) => {
-                    if let FType::Once(f) = std::mem::replace(&mut self.f, FType::None) {
-                        f();
+                    if let FType::Once(f) = std::mem::replace(f, FType::None) {
+                        f(&tick);
                     }
+                    Ok(TickControl::Continue)
                 }
-                FType::Mut(ref mut f) => { (*f)(); }
-                FType::None => ()
+                FType::Mut(ref mut f) => (*f)(&tick),
+                FType::None => Ok(TickControl::Continue)
+            }));
+            if let Err(e) = self.last_tick.clone().set_state(Some(tick)) {
+                println!("WARNING: cannot signal the tick of a timer. Error = {e:?}");
+            }
+            result = match outcome {
+                Ok(Ok(control)) => {
+                    self.failures.store(0, Ordering::SeqCst);
+                    self.apply(control)
+                },
+                Ok(Err(e)) => self.fail(&tick, e),
+                Err(payload) => self.recover(payload)
+            };
+            if self.status() == TimerStatus::Active && self.is_last(&tick) {
+                result = result.and(self.complete());
             }
         }
         drop(section);
-        Ok(())
+        result
     }
 
-    fn wait_idle(&self, acceptable_execution_time: Duration) -> Result<()> {
-        match self.idle.wait(Some(acceptable_execution_time), |v| *v == 0) {
+    fn wait_idle(&self, acceptable_execution_time: Option<Duration>) -> Result<()> {
+        match self.idle.wait(acceptable_execution_time, |v| *v == 0) {
             Ok(_) => Ok(()),
             Err(e) => Err(e.into())
         }
     }
 }
 
-impl<'a> CriticalSection<'a> {
-    fn start(idle: &'a mut IdleWaitType) -> Self {
+/// Wait until `event` holds a tick accepted by `checker`. A timeout is reported as [`TimerError::Elapsed`].
+fn wait_tick(event: &WaitEvent<Option<TickInfo>>, timeout: Option<Duration>, mut checker: impl FnMut(&TickInfo) -> bool) -> Result<TickInfo> {
+    match event.wait(timeout, |tick| tick.as_ref().is_some_and(&mut checker)) {
+        Ok(tick) => tick.ok_or(TimerError::SynchronizationBroken),
+        Err(WaitObjectError::Timeout) => Err(TimerError::Elapsed),
+        Err(e) => Err(e.into())
+    }
+}
+
+impl<'h> Drop for MutWrapper<'h> {
+    fn drop(&mut self) {
+        unregister(self.id);
+    }
+}
+
+impl TimerArm {
+    #[inline]
+    pub fn arm(&self, due: Duration) -> Result<()> {
+        platform::rearm_timer(&self.core, self.handle, due, platform::ONESHOT_PERIOD)
+    }
+
+    /// Arm the timer from its handler, where an error has no caller to go to, so it is printed as a warning about the
+    /// `owner`.
+    pub fn arm_or_warn(&self, due: Duration, owner: &str) {
+        if let Err(e) = self.arm(due) {
+            println!("WARNING: cannot re-arm the {owner}. Error = {e:?}");
+        }
+    }
+}
+
+impl DeadlineArm {
+    #[inline]
+    pub fn attach(&mut self, arm: TimerArm) {
+        self.arm = Some(arm);
+    }
+
+    /// Make sure the timer fires no later than `due`.
+    pub fn arm_before(&mut self, now: Instant, due: Instant) -> Result<()> {
+        if self.armed_at.is_some_and(|armed| armed <= due) {
+            return Ok(());
+        }
+        self.armed_at = Some(due);
+        self.arm.as_ref().map_or(Ok(()), |arm| arm.arm(due.saturating_duration_since(now)))
+    }
+
+    /// Forget the armed deadline when the timer fires, so the next [`DeadlineArm::arm_before`] arms it again.
+    #[inline]
+    pub fn fired(&mut self) {
+        self.armed_at = None;
+    }
+}
+
+impl QueueSettings {
+    pub fn set_error_sink(&self, sink: Option<sync::Arc<ErrorSink>>) {
+        *self.error_sink.write() = sink;
+    }
+
+    pub fn set_panic_policy(&self, policy: PanicPolicy) {
+        *self.panic_policy.lock() = policy;
+    }
+
+    #[inline]
+    fn panic_policy(&self) -> PanicPolicy {
+        *self.panic_policy.lock()
+    }
+
+    fn report(&self, failure: &TimerFailure) {
+        let sink = self.error_sink.read().clone();
+        match sink {
+            // like a handler, a panicking sink must not unwind into the OS timer thread or the queue's dispatcher.
+            Some(sink) => if let Err(payload) = panic::catch_unwind(panic::AssertUnwindSafe(|| sink(failure))) {
+                let message = panic_message(payload.as_ref());
+                if self.panic_policy() == PanicPolicy::Abort {
+                    println!("ERROR: timer error sink panicked, aborting: {message}");
+                    std::process::abort();
+                }
+                println!("WARNING: timer error sink panicked: {message}");
+            },
+            None => println!("WARNING: timer handler failed ({} in a row): {}", failure.consecutive_failures, failure.error)
+        }
+    }
+}
+
+impl Reaper {
+    pub fn new() -> Self {
+        let (sender, jobs) = mpsc::channel::<ReaperJob>();
+        thread::spawn(move || {
+            for job in jobs {
+                job();
+            }
+        });
+        Reaper(sender)
+    }
+
+    pub fn dispatch(&self, job: ReaperJob) -> Result<()> {
+        self.0.send(job).map_err(|_| TimerError::SynchronizationBroken)
+    }
+}
+
+impl CriticalSection {
+    fn start(idle: &IdleWaitType) -> Self {
+        let mut idle = idle.clone();
         idle.set_state_func(|v| *v + 1).unwrap();
         Self(idle)
     }
 }
 
-impl<'a> Drop for CriticalSection<'a> {
+impl Drop for CriticalSection {
     fn drop(&mut self) {
         self.0.set_state_func(|v|{
             let new_value = *v - 1;
@@ -145,4 +645,4 @@ impl<'a> Drop for CriticalSection<'a> {
             new_value
         }).unwrap();
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,147 @@
+use std::{
+    future::Future, mem,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+    sync
+};
+use parking_lot::{Condvar, Mutex};
+use crate::{Result, TimerError};
+
+// ------------------------------------- DATA STRUCTURES & MARKERS ------------------------------------
+/// A result of an operation that completes in background, e.g. [`Timer::close_async`](crate::Timer::close_async).
+///
+/// The result can be waited for from a blocking thread with [`Completion::wait`], polled with [`Completion::try_get`], or
+/// `.await`ed since `Completion` is also a [`Future`].
+///
+/// # Examples
+///
+/// ```
+/// # use std::{thread, time::Duration};
+/// use native_timer::{CallbackHint, TimerQueue};
+///
+/// let hint = Some(CallbackHint::SlowFunction(Duration::from_secs(1)));
+/// let timer = TimerQueue::default().schedule_oneshot(Duration::from_millis(10), hint, || {
+///     thread::sleep(Duration::from_millis(300));
+/// }).unwrap();
+/// thread::sleep(Duration::from_millis(100));
+///
+/// // closing waits for the running task in background.
+/// let mut closing = timer.close_async();
+/// assert!(closing.try_get().is_none());
+/// assert!(!closing.is_completed());
+/// assert!(closing.wait().is_ok());
+/// ```
+pub struct Completion<T>(sync::Arc<CompletionState<T>>);
+
+/// The writing side of a [`Completion`]. If the source is dropped without a result, the waiting side gets
+/// [`TimerError::SynchronizationBroken`].
+pub(crate) struct CompletionSource<T>(Option<sync::Arc<CompletionState<T>>>);
+
+struct CompletionState<T> {
+    value: Mutex<Slot<T>>,
+    signal: Condvar,
+    waker: Mutex<Option<Waker>>
+}
+
+enum Slot<T> {
+    Pending,
+    Ready(Result<T>),
+    Abandoned,
+    Taken
+}
+
+// --------------------------------------- IMPLEMENTATIONS --------------------------------------------
+impl<T> Completion<T> {
+    pub(crate) fn new() -> (CompletionSource<T>, Completion<T>) {
+        let state = sync::Arc::new(CompletionState {
+            value: Mutex::new(Slot::Pending),
+            signal: Condvar::new(),
+            waker: Mutex::new(None)
+        });
+        (CompletionSource(Some(state.clone())), Completion(state))
+    }
+
+    /// Check whether the operation has finished, without taking the result.
+    pub fn is_completed(&self) -> bool {
+        !matches!(*self.0.value.lock(), Slot::Pending)
+    }
+
+    /// Block the current thread until the operation finishes, and return its result.
+    pub fn wait(mut self) -> Result<T> {
+        self.take(None).unwrap_or(Err(TimerError::SynchronizationBroken))
+    }
+
+    /// Wait for the result up to `timeout`. `None` is returned if the operation is still pending, or the result has
+    /// already been taken.
+    pub fn wait_timeout(&mut self, timeout: Duration) -> Option<Result<T>> {
+        self.take(Some(timeout))
+    }
+
+    /// Take the result if it is available, without blocking.
+    pub fn try_get(&mut self) -> Option<Result<T>> {
+        self.take(Some(Duration::ZERO))
+    }
+
+    fn take(&mut self, timeout: Option<Duration>) -> Option<Result<T>> {
+        // a timeout too large for an `Instant` is the same as no timeout.
+        let deadline = timeout.and_then(|t| Instant::now().checked_add(t));
+        let mut guard = self.0.value.lock();
+        while matches!(*guard, Slot::Pending) {
+            match deadline {
+                Some(d) => if self.0.signal.wait_until(&mut guard, d).timed_out() { break; },
+                None => self.0.signal.wait(&mut guard)
+            }
+        }
+        // a pending operation is left as is, so it can still be waited for after a timeout.
+        if matches!(*guard, Slot::Pending) {
+            return None;
+        }
+        match mem::replace(&mut *guard, Slot::Taken) {
+            Slot::Ready(r) => Some(r),
+            Slot::Abandoned => Some(Err(TimerError::SynchronizationBroken)),
+            Slot::Pending | Slot::Taken => None
+        }
+    }
+}
+
+impl<T> Future for Completion<T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        // register the waker before checking, so a completion in between cannot be missed.
+        *this.0.waker.lock() = Some(cx.waker().clone());
+        match this.try_get() {
+            Some(r) => Poll::Ready(r),
+            None => Poll::Pending
+        }
+    }
+}
+
+impl<T> CompletionSource<T> {
+    pub fn complete(mut self, result: Result<T>) {
+        if let Some(state) = self.0.take() {
+            state.finish(Slot::Ready(result));
+        }
+    }
+}
+
+impl<T> Drop for CompletionSource<T> {
+    fn drop(&mut self) {
+        if let Some(state) = self.0.take() {
+            state.finish(Slot::Abandoned);
+        }
+    }
+}
+
+impl<T> CompletionState<T> {
+    fn finish(&self, slot: Slot<T>) {
+        *self.value.lock() = slot;
+        self.signal.notify_all();
+        if let Some(waker) = self.waker.lock().take() {
+            waker.wake();
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant}
+};
+use parking_lot::Mutex;
+use crate::{CallbackHint, Result, Timer, TimerQueue, common::TimerArm};
+
+// ------------------------------------- DATA STRUCTURES & MARKERS ------------------------------------
+/// Runs a handler once, a wait time after the last of a burst of triggers.
+///
+/// # Examples
+///
+/// ```
+/// # use std::{thread, time::Duration};
+/// use native_timer::{DebounceOptions, Debouncer, TimerQueue};
+///
+/// let mut saved = 0;
+/// let debouncer = Debouncer::new(TimerQueue::default(), Duration::from_millis(100), DebounceOptions::default(), || saved += 1).unwrap();
+/// for _ in 0..5 {
+///     debouncer.trigger().unwrap();
+///     thread::sleep(Duration::from_millis(20));
+/// }
+/// thread::sleep(Duration::from_millis(300));
+/// drop(debouncer);
+/// assert_eq!(saved, 1);
+/// ```
+pub struct Debouncer<'h> {
+    shared: Arc<Mutex<DebounceState>>,
+    options: DebounceOptions,
+    wait: Duration,
+    _timer: Timer<'h>
+}
+
+/// Options of a [`Debouncer`]. By default, the handler runs on the trailing edge only, without a maximum wait.
+#[derive(Copy, Clone, Debug)]
+pub struct DebounceOptions {
+    leading: bool,
+    trailing: bool,
+    max_wait: Option<Duration>,
+    hint: Option<CallbackHint>
+}
+
+struct DebounceState {
+    /// Time of the first trigger of the current burst, or since the last call forced by the maximum wait.
+    burst: Option<Instant>,
+    last: Instant,
+    pending: bool,
+    leading_due: bool,
+    arm: Option<TimerArm>
+}
+
+// --------------------------------------- IMPLEMENTATIONS --------------------------------------------
+impl<'h> Debouncer<'h> {
+    /// Create a debouncer on `queue`, which calls `handler` when `wait` has passed since the last trigger.
+    pub fn new<F>(queue: &TimerQueue, wait: Duration, options: DebounceOptions, mut handler: F) -> Result<Self>
+        where F: FnMut() + Send + 'h
+    {
+        let shared = Arc::new(Mutex::new(DebounceState { burst: None, last: Instant::now(), pending: false, leading_due: false, arm: None }));
+        let state = shared.clone();
+        let (timer, arm) = queue.schedule_manual(options.hint, move |_| {
+            let call = {
+                let mut s = state.lock();
+                s.fire(Instant::now(), wait, &options)
+            };
+            if call {
+                handler();
+            }
+        })?;
+        shared.lock().arm = Some(arm);
+        Ok(Debouncer { shared, options, wait, _timer: timer })
+    }
+
+    /// Signal an event. The handler runs after the events stop for the wait time, or right away on the leading edge.
+    pub fn trigger(&self) -> Result<()> {
+        let now = Instant::now();
+        let mut s = self.shared.lock();
+        s.last = now;
+        if s.burst.is_none() {
+            s.burst = Some(now);
+            if self.options.leading {
+                s.leading_due = true;
+                return s.arm.as_ref().map_or(Ok(()), |arm| arm.arm(Duration::ZERO));
+            }
+        }
+        if s.leading_due {
+            // the leading call covers this trigger, and it arms the timer for the rest of the burst.
+            return Ok(());
+        }
+        s.pending = true;
+        let due = s.next_due(now, self.wait, &self.options);
+        s.arm.as_ref().map_or(Ok(()), |arm| arm.arm(due))
+    }
+
+    /// Drop a pending trailing call.
+    pub fn cancel(&self) {
+        let mut s = self.shared.lock();
+        s.burst = None;
+        s.pending = false;
+        s.leading_due = false;
+    }
+
+    /// Check whether a call is waiting for the end of the burst.
+    pub fn is_pending(&self) -> bool {
+        let s = self.shared.lock();
+        s.pending || s.leading_due
+    }
+}
+
+impl DebounceOptions {
+    /// Also run the handler on the first trigger of a burst.
+    ///
+    /// ```
+    /// # use std::{sync::{Arc, atomic::{AtomicU32, Ordering}}, thread, time::Duration};
+    /// use native_timer::{DebounceOptions, Debouncer, TimerQueue};
+    ///
+    /// let calls = Arc::new(AtomicU32::new(0));
+    /// let counter = calls.clone();
+    /// let options = DebounceOptions::default().with_leading(true);
+    /// let debouncer = Debouncer::new(TimerQueue::default(), Duration::from_millis(100), options, move || {
+    ///     counter.fetch_add(1, Ordering::SeqCst);
+    /// }).unwrap();
+    ///
+    /// debouncer.trigger().unwrap();
+    /// thread::sleep(Duration::from_millis(30));
+    /// assert_eq!(calls.load(Ordering::SeqCst), 1);
+    ///
+    /// debouncer.trigger().unwrap();
+    /// thread::sleep(Duration::from_millis(300));
+    /// assert_eq!(calls.load(Ordering::SeqCst), 2);
+    /// ```
+    pub fn with_leading(self, leading: bool) -> Self {
+        DebounceOptions { leading, ..self }
+    }
+
+    /// Run the handler at the end of a burst. This is on by default.
+    pub fn with_trailing(self, trailing: bool) -> Self {
+        DebounceOptions { trailing, ..self }
+    }
+
+    /// Run the handler at least once per `max_wait` while triggers keep coming.
+    pub fn with_max_wait(self, max_wait: Duration) -> Self {
+        DebounceOptions { max_wait: Some(max_wait), ..self }
+    }
+
+    /// Scheduler hint for the handler. See [`CallbackHint`].
+    pub fn with_hint(self, hint: CallbackHint) -> Self {
+        DebounceOptions { hint: Some(hint), ..self }
+    }
+}
+
+impl Default for DebounceOptions {
+    fn default() -> Self {
+        DebounceOptions { leading: false, trailing: true, max_wait: None, hint: None }
+    }
+}
+
+impl DebounceState {
+    /// Time until the end of the burst, or until the maximum wait is reached.
+    fn next_due(&self, now: Instant, wait: Duration, options: &DebounceOptions) -> Duration {
+        let quiet = self.last + wait;
+        let due = match (self.burst, options.max_wait) {
+            (Some(burst), Some(max_wait)) => quiet.min(burst + max_wait),
+            _ => quiet
+        };
+        due.saturating_duration_since(now)
+    }
+
+    /// Handle a tick of the timer, and return whether the handler should be called.
+    fn fire(&mut self, now: Instant, wait: Duration, options: &DebounceOptions) -> bool {
+        let Some(burst) = self.burst else { return false };
+        let call = if self.leading_due {
+            self.leading_due = false;
+            true
+        } else if now >= self.last + wait {
+            self.burst = None;
+            self.pending && options.trailing
+        } else if options.max_wait.is_some_and(|max_wait| now >= burst + max_wait) {
+            self.burst = Some(now);
+            self.pending
+        } else {
+            false
+        };
+        if call || self.burst.is_none() {
+            self.pending = false;
+        }
+        if let (Some(_), Some(arm)) = (self.burst, &self.arm) {
+            arm.arm_or_warn(self.next_due(now, wait, options), "debouncer");
+        }
+        call
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant}
+};
+use parking_lot::{Condvar, Mutex};
+use crate::{Result, Timer, TimerQueue, common::DeadlineArm};
+
+// ------------------------------------- DATA STRUCTURES & MARKERS ------------------------------------
+/// A queue of items which become available after a delay. Consumers receive the items in the order of their deadlines, by
+/// blocking with [`DelayQueue::pop`], or by `.await`ing [`DelayQueue::next`].
+///
+/// The queue is driven by a single timer, which is armed to the earliest deadline.
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// use native_timer::{DelayQueue, TimerQueue};
+///
+/// let queue = DelayQueue::new(TimerQueue::default()).unwrap();
+/// queue.insert("later", Duration::from_millis(200));
+/// let cancelled = queue.insert("never", Duration::from_millis(50));
+/// queue.insert("sooner", Duration::from_millis(100));
+/// assert_eq!(queue.remove(cancelled), Some("never"));
+///
+/// assert_eq!(queue.pop(), "sooner");
+/// assert_eq!(queue.pop_timeout(Duration::from_millis(500)), Some("later"));
+/// assert_eq!(queue.try_pop(), None);
+/// ```
+pub struct DelayQueue<T> {
+    shared: Arc<DelayShared<T>>,
+    _timer: Timer<'static>
+}
+
+/// Identity of an item in a [`DelayQueue`], for removing or rescheduling the item.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DelayKey(u64);
+
+/// A future of the next available item of a [`DelayQueue`], created by [`DelayQueue::next`].
+pub struct Next<'a, T>(&'a DelayQueue<T>);
+
+struct DelayShared<T> {
+    state: Mutex<DelayState<T>>,
+    available: Condvar
+}
+
+struct DelayState<T> {
+    pending: HashMap<u64, (Instant, T)>,
+    deadlines: BTreeSet<(Instant, u64)>,
+    ready: VecDeque<(u64, T)>,
+    next_key: u64,
+    wakers: Vec<Waker>,
+    arm: DeadlineArm
+}
+
+// --------------------------------------- IMPLEMENTATIONS --------------------------------------------
+impl<T: Send + 'static> DelayQueue<T> {
+    /// Create an empty queue, driven by a timer of `queue`.
+    pub fn new(queue: &TimerQueue) -> Result<Self> {
+        let shared = Arc::new(DelayShared {
+            state: Mutex::new(DelayState {
+                pending: HashMap::new(), deadlines: BTreeSet::new(), ready: VecDeque::new(), next_key: 0,
+                wakers: Vec::new(), arm: DeadlineArm::default()
+            }),
+            available: Condvar::new()
+        });
+        let delay_queue = shared.clone();
+        let (timer, arm) = queue.schedule_manual(None, move |_| {
+            let wakers = delay_queue.state.lock().release(Instant::now());
+            if let Some(wakers) = wakers {
+                delay_queue.available.notify_all();
+                wakers.into_iter().for_each(Waker::wake);
+            }
+        })?;
+        shared.state.lock().arm.attach(arm);
+        Ok(DelayQueue { shared, _timer: timer })
+    }
+}
+
+impl<T> DelayQueue<T> {
+    /// Add an item, which becomes available after `delay`.
+    pub fn insert(&self, item: T, delay: Duration) -> DelayKey {
+        let mut s = self.shared.state.lock();
+        s.next_key += 1;
+        let key = s.next_key;
+        s.schedule(key, item, Instant::now() + delay);
+        DelayKey(key)
+    }
+
+    /// Remove an item, whether it is available or not. `None` is returned if the item has been taken.
+    pub fn remove(&self, key: DelayKey) -> Option<T> {
+        self.shared.state.lock().take(key.0)
+    }
+
+    /// Make an item available after `delay` from now, instead of its former deadline. Returns `false` if the item has been
+    /// taken.
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// use native_timer::{DelayQueue, TimerQueue};
+    ///
+    /// let queue = DelayQueue::new(TimerQueue::default()).unwrap();
+    /// let first = queue.insert("first", Duration::from_millis(100));
+    /// queue.insert("second", Duration::from_millis(200));
+    /// assert!(queue.reset(first, Duration::from_millis(300)));
+    ///
+    /// assert_eq!(queue.pop(), "second");
+    /// assert_eq!(queue.pop_timeout(Duration::MAX), Some("first"));
+    /// assert!(!queue.reset(first, Duration::ZERO));
+    /// ```
+    pub fn reset(&self, key: DelayKey, delay: Duration) -> bool {
+        let mut s = self.shared.state.lock();
+        match s.take(key.0) {
+            Some(item) => {
+                s.schedule(key.0, item, Instant::now() + delay);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Block until an item is available, and take it.
+    pub fn pop(&self) -> T {
+        let mut s = self.shared.state.lock();
+        loop {
+            if let Some((_, item)) = s.ready.pop_front() {
+                return item;
+            }
+            self.shared.available.wait(&mut s);
+        }
+    }
+
+    /// Wait up to `timeout` for an available item. A timeout too large for an [`Instant`] waits without a limit.
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut s = self.shared.state.lock();
+        loop {
+            if let Some((_, item)) = s.ready.pop_front() {
+                return Some(item);
+            }
+            match deadline {
+                Some(d) => if self.shared.available.wait_until(&mut s, d).timed_out() {
+                    return s.ready.pop_front().map(|(_, item)| item);
+                },
+                None => self.shared.available.wait(&mut s)
+            }
+        }
+    }
+
+    /// Take an available item without blocking.
+    pub fn try_pop(&self) -> Option<T> {
+        self.shared.state.lock().ready.pop_front().map(|(_, item)| item)
+    }
+
+    /// Get a future of the next available item.
+    ///
+    /// ```
+    #[doc = include_str!("../docs/block_on.md")]
+    /// # use std::time::Duration;
+    /// use native_timer::{DelayQueue, TimerQueue};
+    ///
+    /// let queue = DelayQueue::new(TimerQueue::default()).unwrap();
+    /// queue.insert(2, Duration::from_millis(200));
+    /// queue.insert(1, Duration::from_millis(100));
+    /// assert_eq!(block_on(async { queue.next().await + queue.next().await * 10 }), 21);
+    /// ```
+    #[inline]
+    pub fn next(&self) -> Next<'_, T> {
+        Next(self)
+    }
+
+    /// Number of items in the queue, available or not.
+    pub fn len(&self) -> usize {
+        let s = self.shared.state.lock();
+        s.pending.len() + s.ready.len()
+    }
+
+    /// Check whether the queue has no item.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'a, T> Future for Next<'a, T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut s = self.0.shared.state.lock();
+        match s.ready.pop_front() {
+            Some((_, item)) => Poll::Ready(item),
+            None => {
+                if !s.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                    s.wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> DelayState<T> {
+    fn schedule(&mut self, key: u64, item: T, deadline: Instant) {
+        self.pending.insert(key, (deadline, item));
+        self.deadlines.insert((deadline, key));
+        self.arm_before(Instant::now(), deadline);
+    }
+
+    fn take(&mut self, key: u64) -> Option<T> {
+        if let Some((deadline, item)) = self.pending.remove(&key) {
+            self.deadlines.remove(&(deadline, key));
+            return Some(item);
+        }
+        let index = self.ready.iter().position(|(k, _)| *k == key)?;
+        self.ready.remove(index).map(|(_, item)| item)
+    }
+
+    /// Handle a tick of the timer by moving the due items to the ready list. Returns the wakers to wake if any item is
+    /// released.
+    fn release(&mut self, now: Instant) -> Option<Vec<Waker>> {
+        let mut released = false;
+        while let Some(&(deadline, key)) = self.deadlines.first() {
+            if deadline > now {
+                break;
+            }
+            self.deadlines.pop_first();
+            if let Some((_, item)) = self.pending.remove(&key) {
+                self.ready.push_back((key, item));
+                released = true;
+            }
+        }
+        self.arm.fired();
+        if let Some(&(next, _)) = self.deadlines.first() {
+            self.arm_before(now, next);
+        }
+        released.then(|| mem::take(&mut self.wakers))
+    }
+
+    fn arm_before(&mut self, now: Instant, due: Instant) {
+        if let Err(e) = self.arm.arm_before(now, due) {
+            println!("WARNING: cannot arm the delay queue. Error = {e:?}");
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    sync::Arc,
+    time::{Duration, Instant}
+};
+use parking_lot::Mutex;
+use crate::{CallbackHint, Result, Timer, TimerQueue, common::DeadlineArm};
+
+// ------------------------------------- DATA STRUCTURES & MARKERS ------------------------------------
+/// A map whose entries are evicted after a time to live. Evicted entries are given to an eviction callback, which runs
+/// from the timer queue instead of the caller's thread.
+///
+/// All expirations share one timer, which is armed to the earliest deadline.
+///
+/// # Examples
+///
+/// ```
+/// # use std::{sync::{Arc, Mutex}, thread, time::Duration};
+/// use native_timer::{ExpiringMap, TimerQueue};
+///
+/// let evicted = Arc::new(Mutex::new(Vec::new()));
+/// let log = evicted.clone();
+/// let sessions = ExpiringMap::new(TimerQueue::default(), Duration::from_millis(100), None, move |id, _user: String| {
+///     log.lock().unwrap().push(id);
+/// }).unwrap();
+///
+/// sessions.insert(1, "alice".to_string());
+/// sessions.insert(2, "bob".to_string());
+/// for _ in 0..4 {
+///     thread::sleep(Duration::from_millis(50));
+///     sessions.touch(&1);
+/// }
+/// assert_eq!(sessions.get(&1).as_deref(), Some("alice"));
+/// assert_eq!(sessions.get(&2), None);
+/// assert_eq!(*evicted.lock().unwrap(), [2]);
+/// ```
+pub struct ExpiringMap<'h, K, V> {
+    shared: Arc<Mutex<Entries<K, V>>>,
+    ttl: Duration,
+    _timer: Timer<'h>
+}
+
+struct Entries<K, V> {
+    map: HashMap<K, Entry<V>>,
+    /// Deadlines of the entries, with a sequence number for telling apart entries of the same deadline.
+    deadlines: BTreeMap<(Instant, u64), K>,
+    sequence: u64,
+    arm: DeadlineArm
+}
+
+struct Entry<V> {
+    value: V,
+    ttl: Duration,
+    deadline: (Instant, u64)
+}
+
+// --------------------------------------- IMPLEMENTATIONS --------------------------------------------
+impl<'h, K, V> ExpiringMap<'h, K, V>
+    where K: Eq + Hash + Clone + Send + 'h,
+          V: Send + 'h
+{
+    /// Create a map on `queue`, whose entries live for `ttl` by default. `on_evict` is called with every expired entry.
+    pub fn new<F>(queue: &TimerQueue, ttl: Duration, hint: Option<CallbackHint>, mut on_evict: F) -> Result<Self>
+        where F: FnMut(K, V) + Send + 'h
+    {
+        let shared = Arc::new(Mutex::new(Entries {
+            map: HashMap::new(), deadlines: BTreeMap::new(), sequence: 0, arm: DeadlineArm::default()
+        }));
+        let entries = shared.clone();
+        let (timer, arm) = queue.schedule_manual(hint, move |_| {
+            let expired = entries.lock().expire(Instant::now());
+            for (key, value) in expired {
+                on_evict(key, value);
+            }
+        })?;
+        shared.lock().arm.attach(arm);
+        Ok(ExpiringMap { shared, ttl, _timer: timer })
+    }
+
+    /// Insert an entry with the default time to live, and return the previous value of the key.
+    #[inline]
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.insert_with_ttl(key, value, self.ttl)
+    }
+
+    /// Insert an entry which lives for `ttl`, and return the previous value of the key.
+    pub fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) -> Option<V> {
+        let mut e = self.shared.lock();
+        let previous = e.remove(&key);
+        let deadline = e.schedule(key.clone(), Instant::now() + ttl);
+        e.map.insert(key, Entry { value, ttl, deadline });
+        previous
+    }
+
+    /// Remove an entry without calling the eviction callback.
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.shared.lock().remove(key)
+    }
+
+    /// Extend the life of an entry by its time to live from now. Returns `false` if the entry does not exist.
+    pub fn touch(&self, key: &K) -> bool {
+        let mut e = self.shared.lock();
+        let now = Instant::now();
+        let Some(entry) = e.map.get(key).filter(|entry| entry.deadline.0 > now) else { return false };
+        let (old, ttl) = (entry.deadline, entry.ttl);
+
+        e.deadlines.remove(&old);
+        let deadline = e.schedule(key.clone(), now + ttl);
+        if let Some(entry) = e.map.get_mut(key) {
+            entry.deadline = deadline;
+        }
+        true
+    }
+
+    /// Get a copy of a live entry's value.
+    pub fn get(&self, key: &K) -> Option<V> where V: Clone {
+        let now = Instant::now();
+        self.shared.lock().map.get(key).filter(|entry| entry.deadline.0 > now).map(|entry| entry.value.clone())
+    }
+
+    /// Check whether a live entry exists.
+    pub fn contains_key(&self, key: &K) -> bool {
+        let now = Instant::now();
+        self.shared.lock().map.get(key).is_some_and(|entry| entry.deadline.0 > now)
+    }
+
+    /// Number of entries, including expired entries which are not evicted yet.
+    pub fn len(&self) -> usize {
+        self.shared.lock().map.len()
+    }
+
+    /// Check whether the map has no entry.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> Entries<K, V> {
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.map.remove(key)?;
+        self.deadlines.remove(&entry.deadline);
+        Some(entry.value)
+    }
+
+    /// Record the deadline of `key`, and make sure the timer fires no later than it.
+    fn schedule(&mut self, key: K, deadline: Instant) -> (Instant, u64) {
+        self.sequence += 1;
+        let slot = (deadline, self.sequence);
+        self.deadlines.insert(slot, key);
+        self.arm_before(Instant::now(), deadline);
+        slot
+    }
+
+    /// Handle a tick of the timer, and return the expired entries.
+    fn expire(&mut self, now: Instant) -> Vec<(K, V)> {
+        let mut expired = Vec::new();
+        while let Some(entry) = self.deadlines.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+            let key = entry.remove();
+            if let Some(e) = self.map.remove(&key) {
+                expired.push((key, e.value));
+            }
+        }
+        self.arm.fired();
+        if let Some(&(next, _)) = self.deadlines.keys().next() {
+            self.arm_before(now, next);
+        }
+        expired
+    }
+
+    fn arm_before(&mut self, now: Instant, due: Instant) {
+        if let Err(e) = self.arm.arm_before(now, due) {
+            println!("WARNING: cannot arm the expiring map. Error = {e:?}");
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, Instant, SystemTime}
+};
+use crate::{CallbackHint, Result, Schedule, Timer, TimerQueue};
+
+// ------------------------------------- DATA STRUCTURES & MARKERS ------------------------------------
+/// Randomization of a delay, so that many timers with the same period do not fire in lock-step.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Jitter {
+    /// The delay varies uniformly by up to the given fraction, e.g. `Uniform(0.1)` gives a delay within ±10%. The fraction
+    /// is clamped to `0.0..=1.0`, and a NaN fraction gives no jitter.
+    Uniform(f64),
+
+    /// The delay is uniformly random between zero and the delay.
+    Full,
+
+    /// The delay is uniformly random between the base delay and three times the previous delay, capped at `max`.
+    Decorrelated { max: Duration }
+}
+
+/// A small seedable pseudo-random generator (SplitMix64) used for jitter. A fixed seed makes the jitter reproducible.
+#[derive(Clone, Debug)]
+pub struct JitterRng(u64);
+
+/// A periodic schedule whose delays are randomized by a [`Jitter`] on every tick.
+///
+/// `Jittered` is also an endless [`Iterator`] of the delays.
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// use native_timer::{Jitter, Jittered};
+///
+/// let period = Duration::from_millis(1000);
+/// let delays: Vec<_> = Jittered::new(period, Jitter::Uniform(0.1)).with_seed(42).take(100).collect();
+/// assert!(delays.iter().all(|d| (900..=1100).contains(&d.as_millis())));
+///
+/// // the same seed gives the same delays.
+/// let again: Vec<_> = Jittered::new(period, Jitter::Uniform(0.1)).with_seed(42).take(100).collect();
+/// assert_eq!(delays, again);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Jittered {
+    period: Duration,
+    jitter: Jitter,
+    rng: JitterRng,
+    previous: Duration
+}
+
+// --------------------------------------- IMPLEMENTATIONS --------------------------------------------
+impl TimerQueue {
+    /// Schedule a periodic timer whose every tick is delayed by `period` randomized with `jitter`. The random generator is
+    /// seeded from the system; for reproducible delays, use [`TimerQueue::schedule_with`] with a seeded [`Jittered`].
+    ///
+    /// The other parameters are the same as [`TimerQueue::schedule_timer`].
+    #[inline]
+    pub fn schedule_jittered<'h, F>(&self, period: Duration, jitter: Jitter, hint: Option<CallbackHint>, handler: F) -> Result<Timer<'h>>
+        where F: FnMut() + Send + 'h
+    {
+        self.schedule_with(Jittered::new(period, jitter), hint, handler)
+    }
+}
+
+impl Jitter {
+    /// Randomize `delay`. `previous` is the last randomized delay, which is only used by [`Jitter::Decorrelated`].
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// use native_timer::{Jitter, JitterRng};
+    ///
+    /// let delay = Duration::from_millis(100);
+    /// assert_eq!(Jitter::Uniform(f64::NAN).apply(delay, delay, &mut JitterRng::new(7)), delay);
+    /// ```
+    pub fn apply(&self, delay: Duration, previous: Duration, rng: &mut JitterRng) -> Duration {
+        match *self {
+            Jitter::Uniform(fraction) => {
+                let fraction = if fraction.is_nan() { 0.0 } else { fraction.clamp(0.0, 1.0) };
+                delay.mul_f64(1.0 + fraction * (2.0 * rng.next_f64() - 1.0))
+            },
+            Jitter::Full => delay.mul_f64(rng.next_f64()),
+            Jitter::Decorrelated { max } => {
+                let upper = previous.max(delay).saturating_mul(3);
+                let next = delay + (upper - delay).mul_f64(rng.next_f64());
+                next.min(max)
+            }
+        }
+    }
+}
+
+impl JitterRng {
+    #[inline]
+    pub fn new(seed: u64) -> Self {
+        JitterRng(seed)
+    }
+
+    /// Create a generator seeded from the system.
+    pub fn from_entropy() -> Self {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos());
+        JitterRng(hasher.finish())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `[0, 1)`.
+    #[inline]
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Jittered {
+    pub fn new(period: Duration, jitter: Jitter) -> Self {
+        Jittered { period, jitter, rng: JitterRng::from_entropy(), previous: period }
+    }
+
+    /// Use a generator with a fixed `seed`.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Jittered { rng: JitterRng::new(seed), ..self }
+    }
+}
+
+impl Iterator for Jittered {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        self.previous = self.jitter.apply(self.period, self.previous, &mut self.rng);
+        Some(self.previous)
+    }
+}
+
+impl Schedule for Jittered {
+    #[inline]
+    fn next_after(&mut self, now: Instant) -> Option<Instant> {
+        self.next().and_then(|d| now.checked_add(d))
+    }
+}
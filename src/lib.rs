@@ -14,6 +14,34 @@ use crate::unix as platform;
 
 mod timer;
 mod common;
+mod completion;
+mod scope;
+mod task;
+mod schedule;
+mod jitter;
+mod retry;
+mod debounce;
+mod throttle;
+mod batch;
+mod watchdog;
+mod expiring;
+mod delay_queue;
+mod rate_limit;
+mod timeout;
 
 pub use platform::{ TimerQueue, Timer };
-pub use timer::*;
\ No newline at end of file
+pub use timer::*;
+pub use completion::Completion;
+pub use scope::{scope, Scope, ScopedTimer};
+pub use task::DelayedTask;
+pub use schedule::{Schedule, Intervals, Instants, Exponential, Aligned, Alignment, schedule_aligned_interval};
+pub use jitter::{Jitter, JitterRng, Jittered};
+pub use retry::{retry, Backoff, RetryPolicy, RetryError, RetryHandle, DEFAULT_ATTEMPT_TIME};
+pub use debounce::{Debouncer, DebounceOptions};
+pub use throttle::{Throttler, ThrottleOptions};
+pub use batch::Batcher;
+pub use watchdog::{Escalation, Watchdog, WatchdogRegistry};
+pub use expiring::ExpiringMap;
+pub use delay_queue::{DelayQueue, DelayKey, Next};
+pub use rate_limit::{RateLimiter, RateMode, Acquire};
+pub use timeout::{with_timeout, with_timeout_or};
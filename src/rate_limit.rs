@@ -0,0 +1,187 @@
+use std::{
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant}
+};
+use parking_lot::{Condvar, Mutex};
+use crate::{Result, Timer, TimerQueue};
+
+// ------------------------------------- DATA STRUCTURES & MARKERS ------------------------------------
+/// How unused permits of a [`RateLimiter`] are kept.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RateMode {
+    /// Unused permits accumulate up to `capacity`, which allows bursts after idle time. The bucket starts full.
+    TokenBucket { capacity: u64 },
+
+    /// Permits of a tick expire on the next tick, so the rate never exceeds the permits per tick.
+    LeakyBucket
+}
+
+/// A rate limiter shared by many threads, whose permits are refilled by a periodic timer.
+///
+/// Waiting acquirers are not served in order; a large request may wait longer than smaller ones arriving later.
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::{Duration, Instant};
+/// use native_timer::{RateLimiter, RateMode, TimerQueue};
+///
+/// // 2 permits every 100ms, with bursts of up to 4.
+/// let limiter = RateLimiter::new(TimerQueue::default(), RateMode::TokenBucket { capacity: 4 }, 2, Duration::from_millis(100)).unwrap();
+/// assert!(limiter.try_acquire(4));
+/// assert!(!limiter.try_acquire(1));
+///
+/// let start = Instant::now();
+/// assert!(limiter.acquire(2, Some(Duration::MAX)));
+/// assert!(start.elapsed() >= Duration::from_millis(50));
+/// ```
+pub struct RateLimiter {
+    shared: Arc<Bucket>,
+    timer: Timer<'static>
+}
+
+/// A future of acquiring permits, created by [`RateLimiter::acquire_async`].
+pub struct Acquire<'a> {
+    limiter: &'a RateLimiter,
+    permits: u64
+}
+
+struct Bucket {
+    state: Mutex<BucketState>,
+    refilled: Condvar
+}
+
+struct BucketState {
+    mode: RateMode,
+    per_tick: u64,
+    tokens: u64,
+    wakers: Vec<Waker>
+}
+
+// --------------------------------------- IMPLEMENTATIONS --------------------------------------------
+impl RateLimiter {
+    /// Create a limiter on `queue`, which gives `per_tick` permits every `period`.
+    pub fn new(queue: &TimerQueue, mode: RateMode, per_tick: u64, period: Duration) -> Result<Self> {
+        let state = BucketState { mode, per_tick, tokens: 0, wakers: Vec::new() };
+        let shared = Arc::new(Bucket { state: Mutex::new(BucketState { tokens: state.capacity(), ..state }), refilled: Condvar::new() });
+        let bucket = shared.clone();
+        let timer = queue.schedule_timer(period, period, None, move || {
+            let wakers = bucket.state.lock().refill();
+            bucket.refilled.notify_all();
+            wakers.into_iter().for_each(Waker::wake);
+        })?;
+        Ok(RateLimiter { shared, timer })
+    }
+
+    /// Take `permits` if they are available now.
+    pub fn try_acquire(&self, permits: u64) -> bool {
+        self.shared.state.lock().take(permits)
+    }
+
+    /// Block until `permits` are available and take them, up to `timeout`. Returns `false` on timeout, or if `permits` is
+    /// larger than the bucket can ever hold. A timeout too large for an [`Instant`] waits without a limit.
+    pub fn acquire(&self, permits: u64, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.and_then(|t| Instant::now().checked_add(t));
+        let mut s = self.shared.state.lock();
+        if permits > s.capacity() {
+            return false;
+        }
+        loop {
+            if s.take(permits) {
+                return true;
+            }
+            match deadline {
+                Some(d) => if self.shared.refilled.wait_until(&mut s, d).timed_out() { return s.take(permits); },
+                None => self.shared.refilled.wait(&mut s)
+            }
+        }
+    }
+
+    /// Get a future which resolves when `permits` are taken. It resolves to `false` if `permits` is larger than the bucket
+    /// can ever hold.
+    ///
+    /// ```
+    #[doc = include_str!("../docs/block_on.md")]
+    /// # use std::time::{Duration, Instant};
+    /// use native_timer::{RateLimiter, RateMode, TimerQueue};
+    ///
+    /// let limiter = RateLimiter::new(TimerQueue::default(), RateMode::LeakyBucket, 1, Duration::from_millis(100)).unwrap();
+    /// let start = Instant::now();
+    /// let acquired = block_on(async {
+    ///     for _ in 0..3 {
+    ///         assert!(limiter.acquire_async(1).await);
+    ///     }
+    ///     limiter.acquire_async(2).await
+    /// });
+    /// assert!(!acquired);
+    /// assert!(start.elapsed() >= Duration::from_millis(150));
+    /// ```
+    #[inline]
+    pub fn acquire_async(&self, permits: u64) -> Acquire<'_> {
+        Acquire { limiter: self, permits }
+    }
+
+    /// Number of permits available now.
+    pub fn available(&self) -> u64 {
+        self.shared.state.lock().tokens
+    }
+
+    /// Change the rate to `per_tick` permits every `period`. The next refill happens one `period` from now.
+    pub fn set_rate(&self, per_tick: u64, period: Duration) -> Result<()> {
+        {
+            let mut s = self.shared.state.lock();
+            s.per_tick = per_tick;
+            s.tokens = s.tokens.min(s.capacity());
+        }
+        self.timer.change_period(period, period)
+    }
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = bool;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<bool> {
+        let mut s = self.limiter.shared.state.lock();
+        if self.permits > s.capacity() {
+            Poll::Ready(false)
+        } else if s.take(self.permits) {
+            Poll::Ready(true)
+        } else {
+            if !s.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                s.wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        }
+    }
+}
+
+impl BucketState {
+    fn capacity(&self) -> u64 {
+        match self.mode {
+            RateMode::TokenBucket { capacity } => capacity,
+            RateMode::LeakyBucket => self.per_tick
+        }
+    }
+
+    fn take(&mut self, permits: u64) -> bool {
+        if self.tokens >= permits {
+            self.tokens -= permits;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Add the permits of a tick, and return the wakers of waiting futures.
+    fn refill(&mut self) -> Vec<Waker> {
+        self.tokens = match self.mode {
+            RateMode::TokenBucket { capacity } => self.tokens.saturating_add(self.per_tick).min(capacity),
+            RateMode::LeakyBucket => self.per_tick
+        };
+        mem::take(&mut self.wakers)
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    future::Future,
+    panic, panic::AssertUnwindSafe,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant}
+};
+use crate::{
+    CallbackHint, Completion, Jitter, JitterRng, Result, TickControl, Timer, TimerError, TimerQueue,
+    common::{MutWrapper, panic_message, timer_state}
+};
+
+// ------------------------------------- DATA STRUCTURES & MARKERS ------------------------------------
+/// How the delay between attempts grows. See [`RetryPolicy`].
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// use native_timer::Backoff;
+///
+/// let fibonacci = Backoff::Fibonacci(Duration::from_millis(100));
+/// let delays: Vec<_> = (1..=6).map(|attempt| fibonacci.delay(attempt).as_millis()).collect();
+/// assert_eq!(delays, [100, 100, 200, 300, 500, 800]);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Backoff {
+    /// The same delay before every retry.
+    Constant(Duration),
+
+    /// The delay starts at `initial`, and grows by `step` on every retry.
+    Linear { initial: Duration, step: Duration },
+
+    /// The delay starts at `initial`, and is multiplied by `factor` on every retry.
+    Exponential { initial: Duration, factor: f64 },
+
+    /// The delays are the Fibonacci numbers (1, 1, 2, 3, 5, ...) times the given unit.
+    Fibonacci(Duration)
+}
+
+/// Rules of [`retry`]: the backoff between attempts, and when to give up.
+///
+/// Operations such as reconnects are expected to block, so attempts are hinted as [`CallbackHint::SlowFunction`] of
+/// [`DEFAULT_ATTEMPT_TIME`] by default. Each attempt then runs on its own thread instead of the queue's shared dispatcher,
+/// and dropping or cancelling a [`RetryHandle`] waits up to that time for a running attempt. Use
+/// [`RetryPolicy::with_hint`] for a longer limit, or [`CallbackHint::QuickFunction`] for quick operations.
+#[derive(Copy, Clone, Debug)]
+pub struct RetryPolicy {
+    backoff: Backoff,
+    max_delay: Option<Duration>,
+    jitter: Option<Jitter>,
+    seed: Option<u64>,
+    max_attempts: Option<u32>,
+    deadline: Option<Duration>,
+    hint: Option<CallbackHint>
+}
+
+/// Reason why [`retry`] did not succeed.
+#[derive(Debug)]
+pub enum RetryError<E> {
+    /// All attempts failed, with the error of the last attempt.
+    Exhausted { attempts: u32, last: E },
+
+    /// The next attempt would be after the deadline, with the error of the last attempt.
+    DeadlineExceeded { attempts: u32, last: E },
+
+    /// The retry was cancelled by [`RetryHandle::cancel`].
+    Cancelled,
+
+    /// The timer driving the attempts failed.
+    Timer(TimerError)
+}
+
+/// A handle of a running [`retry`], for waiting for its outcome or cancelling it. The handle can also be `.await`ed.
+///
+/// Dropping the handle cancels the retry.
+pub struct RetryHandle<T, E> {
+    timer: Timer<'static>,
+    outcome: Completion<std::result::Result<T, RetryError<E>>>
+}
+
+// ----------------------------------------- FUNCTIONS --------------------------------------------------
+/// Run `operation` on the default [`TimerQueue`] until it succeeds, waiting between attempts as the `policy` says.
+/// See [`TimerQueue::retry`].
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// use native_timer::{retry, Backoff, RetryPolicy};
+///
+/// let policy = RetryPolicy::new(Backoff::Exponential { initial: Duration::from_millis(10), factor: 2.0 })
+///     .with_max_attempts(5);
+/// let handle = retry(policy, |attempt| if attempt < 3 { Err("not yet") } else { Ok(attempt) }).unwrap();
+/// assert_eq!(handle.wait().unwrap(), 3);
+/// ```
+#[inline]
+pub fn retry<T, E, F>(policy: RetryPolicy, operation: F) -> Result<RetryHandle<T, E>>
+    where T: Send + 'static,
+          E: Send + 'static,
+          F: FnMut(u32) -> std::result::Result<T, E> + Send + 'static
+{
+    TimerQueue::default().retry(policy, operation)
+}
+
+/// Time that an attempt of [`retry`] is expected to take at most, unless the policy has another hint.
+pub const DEFAULT_ATTEMPT_TIME: Duration = Duration::from_secs(30);
+
+// --------------------------------------- IMPLEMENTATIONS --------------------------------------------
+impl TimerQueue {
+    /// Run `operation` from this queue until it succeeds, or the `policy` gives up. The operation receives the attempt
+    /// number, starting from 1, and the first attempt is made right away.
+    ///
+    /// A panic of the operation ends the retry with [`TimerError::Panicked`], without further attempts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// use native_timer::{Backoff, RetryError, RetryPolicy, TimerError, TimerQueue};
+    ///
+    /// let policy = RetryPolicy::new(Backoff::Constant(Duration::from_millis(100))).with_max_attempts(3);
+    /// let handle = TimerQueue::default().retry(policy, |_| -> Result<(), ()> { panic!("lost connection") }).unwrap();
+    /// assert!(matches!(handle.wait(), Err(RetryError::Timer(TimerError::Panicked(msg))) if msg == "lost connection"));
+    /// ```
+    pub fn retry<T, E, F>(&self, policy: RetryPolicy, mut operation: F) -> Result<RetryHandle<T, E>>
+        where T: Send + 'static,
+              E: Send + 'static,
+              F: FnMut(u32) -> std::result::Result<T, E> + Send + 'static
+    {
+        let (source, outcome) = Completion::new();
+        let mut source = Some(source);
+        let mut rng = policy.seed.map_or_else(JitterRng::from_entropy, JitterRng::new);
+        let deadline = policy.deadline.map(|d| Instant::now() + d);
+        let mut attempts = 0;
+        let mut previous = Duration::ZERO;
+
+        let callback = MutWrapper::new_scheduled(self.0.clone(), policy.hint, move |_| {
+            attempts += 1;
+            let result = match panic::catch_unwind(AssertUnwindSafe(|| operation(attempts))) {
+                Err(payload) => Err(RetryError::Timer(TimerError::Panicked(panic_message(payload.as_ref())))),
+                Ok(Ok(value)) => Ok(value),
+                Ok(Err(e)) if policy.max_attempts.is_some_and(|n| attempts >= n) => Err(RetryError::Exhausted { attempts, last: e }),
+                Ok(Err(e)) => {
+                    let delay = policy.delay(attempts, previous, &mut rng);
+                    if deadline.is_some_and(|d| Instant::now() + delay > d) {
+                        Err(RetryError::DeadlineExceeded { attempts, last: e })
+                    } else {
+                        previous = delay;
+                        return TickControl::ChangePeriod(delay);
+                    }
+                }
+            };
+            if let Some(source) = source.take() {
+                source.complete(Ok(result));
+            }
+            TickControl::Complete
+        });
+        let first = Duration::from_nanos(1);
+        let timer = self.schedule_wrapper(first, first, callback, timer_state(()))?;
+        Ok(RetryHandle { timer, outcome })
+    }
+}
+
+impl Backoff {
+    /// The delay before the retry following the `attempt`th failure.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let n = attempt.max(1);
+        match *self {
+            Backoff::Constant(d) => d,
+            Backoff::Linear { initial, step } => initial.saturating_add(step.saturating_mul(n - 1)),
+            Backoff::Exponential { initial, factor } => {
+                Duration::try_from_secs_f64(initial.as_secs_f64() * factor.powi(n as i32 - 1)).unwrap_or(Duration::MAX)
+            },
+            Backoff::Fibonacci(unit) => {
+                let (mut a, mut b) = (1u32, 1u32);
+                for _ in 1..n {
+                    (a, b) = (b, a.saturating_add(b));
+                }
+                unit.saturating_mul(a)
+            }
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Create a policy of `backoff`, which retries without limits.
+    pub fn new(backoff: Backoff) -> Self {
+        let hint = Some(CallbackHint::SlowFunction(DEFAULT_ATTEMPT_TIME));
+        RetryPolicy { backoff, max_delay: None, jitter: None, seed: None, max_attempts: None, deadline: None, hint }
+    }
+
+    /// Cap every delay at `max`.
+    pub fn with_max_delay(self, max: Duration) -> Self {
+        RetryPolicy { max_delay: Some(max), ..self }
+    }
+
+    /// Randomize every delay, after the cap is applied.
+    pub fn with_jitter(self, jitter: Jitter) -> Self {
+        RetryPolicy { jitter: Some(jitter), ..self }
+    }
+
+    /// Use a fixed `seed` for the jitter.
+    pub fn with_seed(self, seed: u64) -> Self {
+        RetryPolicy { seed: Some(seed), ..self }
+    }
+
+    /// Give up after `attempts` attempts, including the first one.
+    pub fn with_max_attempts(self, attempts: u32) -> Self {
+        RetryPolicy { max_attempts: Some(attempts), ..self }
+    }
+
+    /// Give up when the next attempt would start later than `deadline` after the retry began.
+    pub fn with_deadline(self, deadline: Duration) -> Self {
+        RetryPolicy { deadline: Some(deadline), ..self }
+    }
+
+    /// Scheduler hint for the operation, instead of [`CallbackHint::SlowFunction`] of [`DEFAULT_ATTEMPT_TIME`]. See
+    /// [`CallbackHint`].
+    pub fn with_hint(self, hint: CallbackHint) -> Self {
+        RetryPolicy { hint: Some(hint), ..self }
+    }
+
+    fn delay(&self, attempt: u32, previous: Duration, rng: &mut JitterRng) -> Duration {
+        let delay = self.backoff.delay(attempt);
+        let delay = self.max_delay.map_or(delay, |max| delay.min(max));
+        self.jitter.map_or(delay, |j| j.apply(delay, previous, rng))
+    }
+}
+
+impl<T, E> RetryHandle<T, E> {
+    /// Block until the retry finishes, and return its outcome.
+    pub fn wait(self) -> std::result::Result<T, RetryError<E>> {
+        let RetryHandle { timer, outcome } = self;
+        let result = outcome.wait();
+        drop(timer);
+        flatten(result)
+    }
+
+    /// Wait for the outcome up to `timeout`. `None` is returned if the retry is still running, or its outcome has been
+    /// taken.
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// use native_timer::{retry, Backoff, RetryPolicy};
+    ///
+    /// let policy = RetryPolicy::new(Backoff::Constant(Duration::from_millis(50))).with_max_attempts(4);
+    /// let mut handle = retry(policy, |attempt| if attempt < 4 { Err("not yet") } else { Ok(attempt) }).unwrap();
+    /// assert!(handle.wait_timeout(Duration::from_millis(10)).is_none());
+    /// assert!(!handle.is_finished());
+    /// assert_eq!(handle.wait().unwrap(), 4);
+    /// ```
+    pub fn wait_timeout(&mut self, timeout: Duration) -> Option<std::result::Result<T, RetryError<E>>> {
+        self.outcome.wait_timeout(timeout).map(flatten)
+    }
+
+    /// Check whether the retry has finished.
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.outcome.is_completed()
+    }
+
+    /// Stop retrying. An attempt in progress is waited for, and the retry ends with [`RetryError::Cancelled`] if it had not
+    /// finished yet.
+    pub fn cancel(self) -> std::result::Result<T, RetryError<E>> {
+        let RetryHandle { mut timer, mut outcome } = self;
+        timer.close().map_err(RetryError::Timer)?;
+        drop(timer);
+        outcome.try_get().map_or(Err(RetryError::Cancelled), flatten)
+    }
+}
+
+impl<T, E> Future for RetryHandle<T, E> {
+    type Output = std::result::Result<T, RetryError<E>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.get_mut().outcome).poll(cx).map(flatten)
+    }
+}
+
+fn flatten<T, E>(result: Result<std::result::Result<T, RetryError<E>>>) -> std::result::Result<T, RetryError<E>> {
+    match result {
+        Ok(r) => r,
+        Err(TimerError::SynchronizationBroken) => Err(RetryError::Cancelled),
+        Err(e) => Err(RetryError::Timer(e))
+    }
+}
+
+impl<E: Display> Display for RetryError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RetryError::Exhausted { attempts, last } => write!(f, "gave up after {attempts} attempts: {last}"),
+            RetryError::DeadlineExceeded { attempts, last } => write!(f, "deadline exceeded after {attempts} attempts: {last}"),
+            RetryError::Cancelled => write!(f, "retry cancelled"),
+            RetryError::Timer(e) => write!(f, "timer error: {e}")
+        }
+    }
+}
+
+impl<E: Debug + Display> std::error::Error for RetryError<E> {}
@@ -0,0 +1,250 @@
+use std::time::{Duration, Instant, SystemTime};
+use crate::{CallbackHint, Result, TickControl, Timer, TimerQueue, common::{MutWrapper, timer_state}};
+
+// ------------------------------------- DATA STRUCTURES & MARKERS ------------------------------------
+/// A sequence of times when a timer should fire. See [`TimerQueue::schedule_with`].
+pub trait Schedule {
+    /// Get the next time to fire, strictly after the call at `now`. `None` ends the schedule.
+    ///
+    /// This is called once before the timer is armed, then after every tick. A time in the past fires as soon as possible.
+    fn next_after(&mut self, now: Instant) -> Option<Instant>;
+}
+
+/// A schedule from an iterator of delays, each delay counted from the previous tick.
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// use native_timer::{Intervals, TimerQueue};
+///
+/// let delays = [50, 100, 150].map(Duration::from_millis);
+/// let mut ticks = 0;
+/// let timer = TimerQueue::default().schedule_with(Intervals::new(delays), None, || ticks += 1).unwrap();
+/// assert_eq!(timer.wait_completed(Some(Duration::from_secs(1))).unwrap().count, 3);
+/// drop(timer);
+/// assert_eq!(ticks, 3);
+/// ```
+pub struct Intervals<I>(I);
+
+/// A schedule from an iterator of instants. Instants which are not after the previous one fire right away.
+pub struct Instants<I>(I);
+
+/// An endless sequence of delays, growing by `factor` on each tick, optionally capped by a maximum delay.
+///
+/// `Exponential` is also an [`Iterator`] of the delays, so it can be limited by iterator adapters and wrapped with
+/// [`Intervals`].
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// use native_timer::Exponential;
+///
+/// let delays: Vec<_> = Exponential::new(Duration::from_millis(100), 2.0).with_max(Duration::from_millis(500)).take(5).collect();
+/// assert_eq!(delays, [100, 200, 400, 500, 500].map(Duration::from_millis));
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct Exponential {
+    next: Duration,
+    factor: f64,
+    max: Option<Duration>
+}
+
+/// Reference point of an [`Aligned`] schedule.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Alignment {
+    /// Ticks land on multiples of the period since the Unix epoch. E.g. a period of one minute ticks at `hh:mm:00.000`.
+    Epoch,
+
+    /// Ticks land on the anchor plus multiples of the period.
+    Anchor(SystemTime)
+}
+
+/// A periodic schedule aligned to the wall clock, see [`Alignment`].
+///
+/// The next tick is computed from the system clock on every tick, so the schedule realigns itself after the clock jumps.
+/// A schedule with zero period ends right away.
+#[derive(Copy, Clone, Debug)]
+pub struct Aligned {
+    period: Duration,
+    anchor: SystemTime,
+    started: bool
+}
+
+// ----------------------------------------- FUNCTIONS --------------------------------------------------
+/// Schedule a periodic task on the default [`TimerQueue`], whose ticks are aligned to the wall clock. See
+/// [`TimerQueue::schedule_aligned`].
+#[inline]
+pub fn schedule_aligned_interval<'h, F>(period: Duration, alignment: Alignment, hint: Option<CallbackHint>, handler: F) -> Result<Timer<'h>>
+    where F: FnMut() + Send + 'h
+{
+    TimerQueue::default().schedule_aligned(period, alignment, hint, handler)
+}
+
+// --------------------------------------- IMPLEMENTATIONS --------------------------------------------
+impl TimerQueue {
+    /// Schedule a timer which fires at the times given by `schedule`. The native timer is re-armed to the next time after
+    /// each tick, and the timer completes when the schedule ends (see [`Timer::wait_completed`]).
+    ///
+    /// The other parameters are the same as [`TimerQueue::schedule_timer`].
+    pub fn schedule_with<'h, S, F>(&self, mut schedule: S, hint: Option<CallbackHint>, mut handler: F) -> Result<Timer<'h>>
+        where S: Schedule + Send + 'h,
+              F: FnMut() + Send + 'h
+    {
+        let now = Instant::now();
+        let first = schedule.next_after(now);
+        let callback = MutWrapper::new_scheduled(self.0.clone(), hint, move |_| {
+            handler();
+            let now = Instant::now();
+            match schedule.next_after(now) {
+                // the interval becomes the period, which is also used to retry after a recovered panic.
+                Some(next) => TickControl::ChangePeriod(next.saturating_duration_since(now)),
+                None => TickControl::Complete
+            }
+        });
+        let due = match first {
+            // zero due disarms a native timer, but a schedule starting now should fire right away.
+            Some(first) => first.saturating_duration_since(now).max(Duration::from_nanos(1)),
+            None => {
+                callback.complete_unarmed()?;
+                Duration::ZERO
+            }
+        };
+        self.schedule_wrapper(due, due, callback, timer_state(()))
+    }
+
+    /// Schedule a periodic timer whose ticks land on multiples of `period` from the `alignment` point on the wall clock,
+    /// rather than from the time of this call.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    /// use native_timer::{Alignment, TimerQueue};
+    ///
+    /// let mut tick_time = None;
+    /// let period = Duration::from_millis(200);
+    /// let timer = TimerQueue::default().schedule_aligned(period, Alignment::Epoch, None, || {
+    ///     tick_time.get_or_insert(SystemTime::now());
+    /// }).unwrap();
+    /// std::thread::sleep(Duration::from_millis(300));
+    /// drop(timer);
+    ///
+    /// let offset = tick_time.unwrap().duration_since(UNIX_EPOCH).unwrap().as_millis() % 200;
+    /// assert!(offset < 50, "tick is {offset}ms after a boundary");
+    /// ```
+    #[inline]
+    pub fn schedule_aligned<'h, F>(&self, period: Duration, alignment: Alignment, hint: Option<CallbackHint>, handler: F) -> Result<Timer<'h>>
+        where F: FnMut() + Send + 'h
+    {
+        self.schedule_with(Aligned::new(period, alignment), hint, handler)
+    }
+}
+
+impl<S: Schedule + ?Sized> Schedule for Box<S> {
+    #[inline]
+    fn next_after(&mut self, now: Instant) -> Option<Instant> {
+        (**self).next_after(now)
+    }
+}
+
+impl<I: Iterator<Item = Duration>> Intervals<I> {
+    #[inline]
+    pub fn new<T: IntoIterator<IntoIter = I>>(delays: T) -> Self {
+        Intervals(delays.into_iter())
+    }
+}
+
+impl<I: Iterator<Item = Duration>> Schedule for Intervals<I> {
+    #[inline]
+    fn next_after(&mut self, now: Instant) -> Option<Instant> {
+        self.0.next().map(|d| now + d)
+    }
+}
+
+impl<I: Iterator<Item = Instant>> Instants<I> {
+    #[inline]
+    pub fn new<T: IntoIterator<IntoIter = I>>(instants: T) -> Self {
+        Instants(instants.into_iter())
+    }
+}
+
+impl<I: Iterator<Item = Instant>> Schedule for Instants<I> {
+    #[inline]
+    fn next_after(&mut self, _now: Instant) -> Option<Instant> {
+        self.0.next()
+    }
+}
+
+impl Exponential {
+    /// Create a sequence starting at `initial`, multiplied by `factor` on each step.
+    pub fn new(initial: Duration, factor: f64) -> Self {
+        Exponential { next: initial, factor, max: None }
+    }
+
+    /// Cap the delays at `max`.
+    pub fn with_max(self, max: Duration) -> Self {
+        Exponential { next: self.next.min(max), max: Some(max), ..self }
+    }
+}
+
+impl Iterator for Exponential {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let current = self.next;
+        let grown = Duration::try_from_secs_f64(current.as_secs_f64() * self.factor).unwrap_or(Duration::MAX);
+        self.next = self.max.map_or(grown, |max| grown.min(max));
+        Some(current)
+    }
+}
+
+impl Schedule for Exponential {
+    #[inline]
+    fn next_after(&mut self, now: Instant) -> Option<Instant> {
+        self.next().and_then(|d| now.checked_add(d))
+    }
+}
+
+impl Aligned {
+    pub fn new(period: Duration, alignment: Alignment) -> Self {
+        let anchor = match alignment {
+            Alignment::Epoch => SystemTime::UNIX_EPOCH,
+            Alignment::Anchor(t) => t
+        };
+        Aligned { period, anchor, started: false }
+    }
+
+    /// The first boundary strictly after `time`.
+    fn boundary_after(&self, time: SystemTime) -> SystemTime {
+        let period = self.period.as_nanos() as i128;
+        let offset = match time.duration_since(self.anchor) {
+            Ok(d) => d.as_nanos() as i128,
+            Err(e) => -(e.duration().as_nanos() as i128)
+        };
+        let boundary = (offset.div_euclid(period) + 1) * period;
+        if boundary >= 0 { self.anchor + nanos(boundary) } else { self.anchor - nanos(-boundary) }
+    }
+}
+
+impl Schedule for Aligned {
+    fn next_after(&mut self, now: Instant) -> Option<Instant> {
+        if self.period.is_zero() {
+            return None;
+        }
+        let wall = SystemTime::now();
+
+        // a tick may come a bit early, which must not select the boundary of that same tick again.
+        let reference = if self.started { wall + self.period / 2 } else { wall };
+        self.started = true;
+
+        let next = self.boundary_after(reference);
+        Some(now + next.duration_since(wall).unwrap_or_default())
+    }
+}
+
+#[inline]
+fn nanos(n: i128) -> Duration {
+    Duration::new((n / 1_000_000_000) as u64, (n % 1_000_000_000) as u32)
+}
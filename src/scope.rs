@@ -0,0 +1,145 @@
+use std::{marker::PhantomData, time::Duration};
+use parking_lot::Mutex;
+use crate::{CallbackHint, Result, Timer, TimerQueue};
+
+// ------------------------------------- DATA STRUCTURES & MARKERS ------------------------------------
+/// A scope for creating timers whose closures may borrow non-`'static` data. See [`scope`].
+///
+/// All timers created from the scope are owned by the scope, so they are guaranteed to be closed before [`scope`] returns,
+/// even if a [`ScopedTimer`] handle is leaked by `mem::forget`. Closing waits for running handlers however long they take,
+/// instead of the acceptable execution time of [`CallbackHint`].
+pub struct Scope<'scope, 'env: 'scope> {
+    queue: TimerQueue,
+    timers: Mutex<OwnedTimers<'env>>,
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>
+}
+
+/// A handle to a timer created in a [`Scope`].
+pub struct ScopedTimer<'scope, 'env: 'scope> {
+    scope: &'scope Scope<'scope, 'env>,
+    index: usize
+}
+
+/// Timers owned by a [`Scope`], which are closed without a time limit when the scope ends.
+struct OwnedTimers<'env>(Vec<Option<Timer<'env>>>);
+
+// ----------------------------------------- FUNCTIONS --------------------------------------------------
+/// Create a scope for timers on the default [`TimerQueue`]. Every timer created in the scope is closed before this function
+/// returns, which makes it safe for the timers' closures to borrow local variables.
+///
+/// This is similar to [`std::thread::scope`].
+///
+/// # Examples
+///
+/// ```
+/// # use std::{thread, time::Duration};
+/// let mut count = 0;
+/// native_timer::scope(|s| {
+///     s.schedule_interval(Duration::from_millis(100), None, || count += 1).unwrap();
+///     thread::sleep(Duration::from_millis(350));
+/// });
+/// assert_eq!(count, 3);
+/// ```
+///
+/// A handler which is still running when the scope ends is waited for, even beyond
+/// [`DEFAULT_ACCEPTABLE_EXECUTION_TIME`](crate::DEFAULT_ACCEPTABLE_EXECUTION_TIME):
+///
+/// ```
+/// # use std::{thread, time::Duration};
+/// let mut finished = false;
+/// native_timer::scope(|s| {
+///     s.schedule_oneshot(Duration::from_millis(10), None, || {
+///         thread::sleep(Duration::from_millis(1500));
+///         finished = true;
+///     }).unwrap();
+///     thread::sleep(Duration::from_millis(100));
+/// });
+/// assert!(finished);
+/// ```
+#[inline]
+pub fn scope<'env, F, T>(f: F) -> T
+    where F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T
+{
+    TimerQueue::default().scope(f)
+}
+
+// ----------------------------------------- IMPLEMENTATIONS --------------------------------------------
+impl TimerQueue {
+    /// Create a scope for timers on this queue. See [`scope`].
+    pub fn scope<'env, F, T>(&self, f: F) -> T
+        where F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T
+    {
+        let scope = Scope {
+            queue: TimerQueue::new_with_context(self.0.clone()),
+            timers: Mutex::new(OwnedTimers(Vec::new())),
+            scope: PhantomData,
+            env: PhantomData
+        };
+        // timers are closed by dropping the scope, which also happens when `f` panics.
+        f(&scope)
+    }
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+    /// Schedule a timer in the scope. See [`TimerQueue::schedule_timer`].
+    pub fn schedule_timer<F>(&'scope self, due: Duration, period: Duration, hint: Option<CallbackHint>, handler: F) -> Result<ScopedTimer<'scope, 'env>>
+        where F: FnMut() + Send + 'env
+    {
+        let timer = self.queue.schedule_timer(due, period, hint, handler)?;
+        Ok(self.own(timer))
+    }
+
+    /// Schedule an interval task in the scope. See [`schedule_interval`](crate::schedule_interval).
+    #[inline]
+    pub fn schedule_interval<F>(&'scope self, interval: Duration, hint: Option<CallbackHint>, handler: F) -> Result<ScopedTimer<'scope, 'env>>
+        where F: FnMut() + Send + 'env
+    {
+        self.schedule_timer(interval, interval, hint, handler)
+    }
+
+    /// Schedule an one-shot task in the scope. See [`TimerQueue::schedule_oneshot`].
+    pub fn schedule_oneshot<F>(&'scope self, due: Duration, hint: Option<CallbackHint>, handler: F) -> Result<ScopedTimer<'scope, 'env>>
+        where F: FnOnce() + Send + 'env
+    {
+        let timer = self.queue.schedule_oneshot(due, hint, handler)?;
+        Ok(self.own(timer))
+    }
+
+    fn own(&'scope self, timer: Timer<'env>) -> ScopedTimer<'scope, 'env> {
+        let mut timers = self.timers.lock();
+        timers.0.push(Some(timer));
+        ScopedTimer { scope: self, index: timers.0.len() - 1 }
+    }
+}
+
+impl<'scope, 'env> ScopedTimer<'scope, 'env> {
+    /// Reset the timer with a new due time and a new period. Nothing happens if the timer has been closed.
+    pub fn change_period(&self, due: Duration, period: Duration) -> Result<()> {
+        match &self.scope.timers.lock().0[self.index] {
+            Some(timer) => timer.change_period(due, period),
+            None => Ok(())
+        }
+    }
+
+    /// Close the timer before the scope ends.
+    pub fn close(self) -> Result<()> {
+        let timer = self.scope.timers.lock().0[self.index].take();
+        match timer {
+            Some(mut timer) => timer.close_unbounded(),
+            None => Ok(())
+        }
+    }
+}
+
+impl<'env> Drop for OwnedTimers<'env> {
+    fn drop(&mut self) {
+        for mut timer in self.0.drain(..).flatten() {
+            // a handler which may still run must not outlive the borrowed environment.
+            if let Err(e) = timer.close_unbounded() {
+                println!("ERROR: cannot close a scoped timer, aborting. Error = {e:?}");
+                std::process::abort();
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    panic, panic::AssertUnwindSafe,
+    time::Duration
+};
+use crate::{
+    CallbackHint, Completion, Result, Timer, TimerError, TimerQueue,
+    common::{MutWrapper, panic_message, timer_state}
+};
+
+// ------------------------------------- DATA STRUCTURES & MARKERS ------------------------------------
+/// A one-shot task which gives back its result, created by [`TimerQueue::schedule_oneshot_with_result`].
+///
+/// Like [`Timer`], dropping the task before it is due cancels the task.
+pub struct DelayedTask<'h, T> {
+    timer: Timer<'h>,
+    result: Completion<T>
+}
+
+// --------------------------------------- IMPLEMENTATIONS --------------------------------------------
+impl TimerQueue {
+    /// Schedule an one-shot task whose return value can be retrieved from the returned [`DelayedTask`]. If the task panics,
+    /// the panic is reported as [`TimerError::Panicked`].
+    ///
+    /// The details of parameters are similar to [`TimerQueue::schedule_oneshot`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// use native_timer::TimerQueue;
+    ///
+    /// let base = 40;
+    /// let task = TimerQueue::default().schedule_oneshot_with_result(Duration::from_millis(100), None, || base + 2).unwrap();
+    /// assert_eq!(task.join().unwrap(), 42);
+    /// ```
+    pub fn schedule_oneshot_with_result<'h, T, F>(&self, due: Duration, hint: Option<CallbackHint>, handler: F) -> Result<DelayedTask<'h, T>>
+        where T: Send + 'h,
+              F: FnOnce() -> T + Send + 'h
+    {
+        let (source, result) = Completion::new();
+        let callback = MutWrapper::new_once(self.0.clone(), hint, move || {
+            let r = panic::catch_unwind(AssertUnwindSafe(handler));
+            source.complete(r.map_err(|e| TimerError::Panicked(panic_message(e.as_ref()))));
+        });
+        let timer = self.schedule_wrapper(due, Duration::ZERO, callback, timer_state(()))?;
+        Ok(DelayedTask { timer, result })
+    }
+}
+
+impl<'h, T> DelayedTask<'h, T> {
+    /// Block until the task finishes, and return its result.
+    pub fn join(self) -> Result<T> {
+        let DelayedTask { timer, result } = self;
+        let r = result.wait();
+        drop(timer);
+        r
+    }
+
+    /// Wait for the task's result up to `timeout`. `None` is returned if the task is still pending, or its result has been
+    /// taken.
+    ///
+    /// A task which is still pending can be joined later:
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// use native_timer::TimerQueue;
+    ///
+    /// let mut task = TimerQueue::default().schedule_oneshot_with_result(Duration::from_millis(200), None, || 42).unwrap();
+    /// assert!(task.try_get().is_none());
+    /// assert!(task.join_timeout(Duration::from_millis(10)).is_none());
+    /// assert!(!task.is_finished());
+    /// assert_eq!(task.join().unwrap(), 42);
+    /// ```
+    #[inline]
+    pub fn join_timeout(&mut self, timeout: Duration) -> Option<Result<T>> {
+        self.result.wait_timeout(timeout)
+    }
+
+    /// Take the task's result if it has finished, without blocking.
+    ///
+    /// ```
+    /// # use std::{thread, time::Duration};
+    /// use native_timer::{TimerError, TimerQueue};
+    ///
+    /// let mut task = TimerQueue::default().schedule_oneshot_with_result(Duration::from_millis(100), None, || -> i32 {
+    ///     panic!("no answer")
+    /// }).unwrap();
+    /// assert!(task.try_get().is_none());
+    /// thread::sleep(Duration::from_millis(300));
+    /// assert!(matches!(task.try_get(), Some(Err(TimerError::Panicked(msg))) if msg == "no answer"));
+    /// ```
+    #[inline]
+    pub fn try_get(&mut self) -> Option<Result<T>> {
+        self.result.try_get()
+    }
+
+    /// Check whether the task has finished.
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.result.is_completed()
+    }
+
+    /// Access the underlying timer of the task.
+    #[inline]
+    pub fn timer(&self) -> &Timer<'h> {
+        &self.timer
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant}
+};
+use parking_lot::Mutex;
+use crate::{CallbackHint, Result, Timer, TimerQueue, common::TimerArm};
+
+// ------------------------------------- DATA STRUCTURES & MARKERS ------------------------------------
+/// Runs a handler at most once per window. By default, the first call of a window runs right away from the timer queue,
+/// and the calls during the window are collapsed into one trailing call with the latest argument. See [`ThrottleOptions`].
+///
+/// # Examples
+///
+/// ```
+/// # use std::{sync::{Arc, Mutex}, thread, time::Duration};
+/// use native_timer::{ThrottleOptions, Throttler, TimerQueue};
+///
+/// let seen = Arc::new(Mutex::new(Vec::new()));
+/// let log = seen.clone();
+/// let throttler = Throttler::new(TimerQueue::default(), Duration::from_millis(100), ThrottleOptions::default(), move |n| {
+///     log.lock().unwrap().push(n);
+/// }).unwrap();
+///
+/// for n in 1..=5 {
+///     throttler.call(n).unwrap();
+/// }
+/// thread::sleep(Duration::from_millis(300));
+/// assert_eq!(*seen.lock().unwrap(), [1, 5]);
+/// ```
+pub struct Throttler<'h, A> {
+    shared: Arc<Mutex<ThrottleState<A>>>,
+    window: Duration,
+    options: ThrottleOptions,
+    _timer: Timer<'h>
+}
+
+/// Options of a [`Throttler`]. By default, the first call of a window is made right away, and a trailing call is made with
+/// the latest argument of the window.
+///
+/// # Examples
+///
+/// ```
+/// # use std::{sync::{Arc, Mutex}, thread, time::Duration};
+/// use native_timer::{ThrottleOptions, Throttler, TimerQueue};
+///
+/// let seen = Arc::new(Mutex::new(Vec::new()));
+/// let log = seen.clone();
+/// let options = ThrottleOptions::default().with_leading(false);
+/// let throttler = Throttler::new(TimerQueue::default(), Duration::from_millis(100), options, move |n| {
+///     log.lock().unwrap().push(n);
+/// }).unwrap();
+///
+/// for n in 1..=5 {
+///     throttler.call(n).unwrap();
+/// }
+/// thread::sleep(Duration::from_millis(50));
+/// assert!(seen.lock().unwrap().is_empty());
+/// thread::sleep(Duration::from_millis(200));
+/// assert_eq!(*seen.lock().unwrap(), [5]);
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct ThrottleOptions {
+    leading: bool,
+    trailing: bool,
+    hint: Option<CallbackHint>
+}
+
+struct ThrottleState<A> {
+    /// End of the current window, `None` if no window is open.
+    window_end: Option<Instant>,
+    leading: Option<A>,
+    latest: Option<A>,
+    arm: Option<TimerArm>
+}
+
+// --------------------------------------- IMPLEMENTATIONS --------------------------------------------
+impl<'h, A: Send + 'h> Throttler<'h, A> {
+    /// Create a throttler on `queue`, which calls `handler` at most once per `window`.
+    pub fn new<F>(queue: &TimerQueue, window: Duration, options: ThrottleOptions, mut handler: F) -> Result<Self>
+        where F: FnMut(A) + Send + 'h
+    {
+        let shared = Arc::new(Mutex::new(ThrottleState { window_end: None, leading: None, latest: None, arm: None }));
+        let state = shared.clone();
+        let (timer, arm) = queue.schedule_manual(options.hint, move |_| {
+            let arg = state.lock().fire(Instant::now(), window);
+            if let Some(arg) = arg {
+                handler(arg);
+            }
+        })?;
+        shared.lock().arm = Some(arm);
+        Ok(Throttler { shared, window, options, _timer: timer })
+    }
+
+    /// Request a call with `arg`. This can be called from any thread.
+    pub fn call(&self, arg: A) -> Result<()> {
+        let now = Instant::now();
+        let mut s = self.shared.lock();
+        if s.window_end.is_none() {
+            s.window_end = Some(now + self.window);
+            let due = if self.options.leading {
+                s.leading = Some(arg);
+                Duration::ZERO
+            } else {
+                if self.options.trailing {
+                    s.latest = Some(arg);
+                }
+                self.window
+            };
+            s.arm.as_ref().map_or(Ok(()), |arm| arm.arm(due))
+        } else {
+            if self.options.trailing {
+                s.latest = Some(arg);
+            }
+            Ok(())
+        }
+    }
+
+    /// Drop a pending trailing call.
+    pub fn cancel(&self) {
+        self.shared.lock().latest = None;
+    }
+}
+
+impl ThrottleOptions {
+    /// Make a call right away with the first argument of a window.
+    pub fn with_leading(self, leading: bool) -> Self {
+        ThrottleOptions { leading, ..self }
+    }
+
+    /// Make a trailing call with the latest argument given during a window.
+    pub fn with_trailing(self, trailing: bool) -> Self {
+        ThrottleOptions { trailing, ..self }
+    }
+
+    /// Scheduler hint for the handler. See [`CallbackHint`].
+    pub fn with_hint(self, hint: CallbackHint) -> Self {
+        ThrottleOptions { hint: Some(hint), ..self }
+    }
+}
+
+impl Default for ThrottleOptions {
+    fn default() -> Self {
+        ThrottleOptions { leading: true, trailing: true, hint: None }
+    }
+}
+
+impl<A> ThrottleState<A> {
+    /// Handle a tick of the timer, and return the argument to call the handler with.
+    fn fire(&mut self, now: Instant, window: Duration) -> Option<A> {
+        let window_end = self.window_end?;
+        if let Some(leading) = self.leading.take() {
+            self.rearm(window_end.saturating_duration_since(now));
+            Some(leading)
+        } else if now < window_end {
+            self.rearm(window_end - now);
+            None
+        } else if let Some(latest) = self.latest.take() {
+            // the trailing call opens a new window.
+            self.window_end = Some(now + window);
+            self.rearm(window);
+            Some(latest)
+        } else {
+            self.window_end = None;
+            None
+        }
+    }
+
+    fn rearm(&self, due: Duration) {
+        if let Some(arm) = &self.arm {
+            arm.arm_or_warn(due, "throttler");
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    panic, panic::AssertUnwindSafe,
+    sync::Arc,
+    thread,
+    time::Duration
+};
+use parking_lot::Mutex;
+use crate::{CallbackHint, Completion, Result, TimerError, TimerQueue, DEFAULT_ACCEPTABLE_EXECUTION_TIME, common::panic_message};
+
+// ----------------------------------------- FUNCTIONS --------------------------------------------------
+/// Run a blocking `operation` on a worker thread, and wait for its result up to `timeout`, by a timer of the default
+/// [`TimerQueue`]. See [`TimerQueue::with_timeout`].
+///
+/// # Examples
+///
+/// ```
+/// # use std::{thread, time::Duration};
+/// use native_timer::{with_timeout, TimerError};
+///
+/// assert_eq!(with_timeout(Duration::from_millis(200), || 42).unwrap(), 42);
+///
+/// let hung = with_timeout(Duration::from_millis(100), || thread::sleep(Duration::from_secs(1)));
+/// assert!(matches!(hung, Err(TimerError::Elapsed)));
+/// ```
+///
+/// The timeout also fires when called from a quick handler of the same queue:
+///
+/// ```
+/// # use std::{thread, time::Duration};
+/// use native_timer::{schedule_oneshot, with_timeout, TimerError};
+///
+/// let mut result = None;
+/// let timer = schedule_oneshot(Duration::from_millis(10), None, || {
+///     result = Some(with_timeout(Duration::from_millis(100), || thread::sleep(Duration::from_millis(500))));
+/// }).unwrap();
+/// thread::sleep(Duration::from_millis(300));
+/// drop(timer);
+/// assert!(matches!(result, Some(Err(TimerError::Elapsed))));
+/// ```
+#[inline]
+pub fn with_timeout<T, F>(timeout: Duration, operation: F) -> Result<T>
+    where T: Send + 'static,
+          F: FnOnce() -> T + Send + 'static
+{
+    TimerQueue::default().with_timeout(timeout, operation)
+}
+
+/// Like [`with_timeout`], but calls `on_elapsed` when the timeout comes first. See [`TimerQueue::with_timeout_or`].
+#[inline]
+pub fn with_timeout_or<T, F, C>(timeout: Duration, operation: F, on_elapsed: C) -> Result<T>
+    where T: Send + 'static,
+          F: FnOnce() -> T + Send + 'static,
+          C: FnOnce() + Send + 'static
+{
+    TimerQueue::default().with_timeout_or(timeout, operation, on_elapsed)
+}
+
+// --------------------------------------- IMPLEMENTATIONS --------------------------------------------
+impl TimerQueue {
+    /// Run a blocking `operation` on a worker thread, and wait for its result up to `timeout`. If the timeout comes first,
+    /// [`TimerError::Elapsed`] is returned; a panic of the operation is reported as [`TimerError::Panicked`].
+    ///
+    /// A thread cannot be killed, so a timed out operation keeps running in background and its result is dropped. Use
+    /// [`TimerQueue::with_timeout_or`] to unblock the operation, e.g. by closing its socket.
+    #[inline]
+    pub fn with_timeout<T, F>(&self, timeout: Duration, operation: F) -> Result<T>
+        where T: Send + 'static,
+              F: FnOnce() -> T + Send + 'static
+    {
+        self.with_timeout_or(timeout, operation, || {})
+    }
+
+    /// Like [`TimerQueue::with_timeout`], but calls `on_elapsed` when the timeout comes first. The call finishes before
+    /// this function returns.
+    ///
+    /// The timeout is a slow function of this queue (see [`CallbackHint::SlowFunction`]), so it fires even when this
+    /// function is called from a handler of the same queue, and `on_elapsed` runs on a timer thread of its own.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::{sync::mpsc, time::Duration};
+    /// use native_timer::{TimerError, TimerQueue};
+    ///
+    /// let (cancel, cancelled) = mpsc::channel::<()>();
+    /// let r = TimerQueue::default().with_timeout_or(Duration::from_millis(100), move || cancelled.recv().is_err(), move || drop(cancel));
+    /// assert!(matches!(r, Err(TimerError::Elapsed)));
+    /// ```
+    pub fn with_timeout_or<T, F, C>(&self, timeout: Duration, operation: F, on_elapsed: C) -> Result<T>
+        where T: Send + 'static,
+              F: FnOnce() -> T + Send + 'static,
+              C: FnOnce() + Send + 'static
+    {
+        let (source, result) = Completion::new();
+        let source = Arc::new(Mutex::new(Some(source)));
+
+        let timer_source = source.clone();
+        let hint = Some(CallbackHint::SlowFunction(DEFAULT_ACCEPTABLE_EXECUTION_TIME));
+        let timer = self.schedule_oneshot(timeout, hint, move || {
+            let Some(s) = timer_source.lock().take() else { return };
+            on_elapsed();
+            s.complete(Err(TimerError::Elapsed));
+        })?;
+
+        thread::spawn(move || {
+            let r = panic::catch_unwind(AssertUnwindSafe(operation));
+            if let Some(s) = source.lock().take() {
+                s.complete(r.map_err(|e| TimerError::Panicked(panic_message(e.as_ref()))));
+            }
+        });
+
+        let r = result.wait();
+        drop(timer);
+        r
+    }
+}
@@ -1,9 +1,12 @@
 use std::{
+    any::Any,
+    error::Error,
     fmt::{Display, Formatter}, fmt,
-    time::Duration
+    time::{Duration, Instant}, sync
 };
+use parking_lot::Mutex;
 use sync_wait_object::WaitObjectError;
-use crate::{TimerQueue, Timer};
+use crate::{TimerQueue, Timer, Completion, platform, common::{MutWrapper, ReaperJob, TimerArm, timer_state}};
 
 /// Scheduler hint about the callback function.
 #[derive(Copy, Clone, Debug)]
@@ -22,11 +25,125 @@ pub enum TimerError {
     OsError(isize, String),
 
     /// A sync object gets broken (or poisoned) due to panic!()
-    SynchronizationBroken
+    SynchronizationBroken,
+
+    /// A task panicked during its execution, with the panic message.
+    Panicked(String),
+
+    /// A task did not finish before its timeout. See [`with_timeout`].
+    Elapsed
 }
 
 pub type Result<T> = std::result::Result<T, TimerError>;
 
+/// Information of a timer's tick, given to the handler on each execution.
+#[derive(Copy, Clone, Debug)]
+pub struct TickInfo {
+    /// Sequence number of the tick, starting from 1.
+    pub count: u64,
+
+    /// Time when the handler is called.
+    pub time: Instant
+}
+
+/// Decision of a controlled timer's handler about the timer's next tick. See [`TimerQueue::schedule_controlled`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TickControl {
+    /// Keep the current schedule.
+    Continue,
+
+    /// Stop the timer after this tick. The timer can be resumed by [`Timer::change_period`].
+    Stop,
+
+    /// Next tick happens after the given duration, then the timer continues with its current period.
+    RescheduleIn(Duration),
+
+    /// Change the timer's period. The next tick is one new period after this tick.
+    ChangePeriod(Duration),
+
+    /// Stop the timer after this tick for good, as its work is done. The timer's status becomes [`TimerStatus::Completed`].
+    Complete
+}
+
+/// Current status of a [`Timer`], see [`Timer::status`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimerStatus {
+    /// The timer is ticking.
+    Active,
+
+    /// The handler stopped the timer by [`TickControl::Stop`].
+    Stopped,
+
+    /// The timer was disabled after its handler failed `failures` times in a row. See [`TimerQueue::schedule_fallible`].
+    Disabled { failures: u32 },
+
+    /// The timer was disabled after its handler panicked, under [`PanicPolicy::DisableTimer`].
+    Panicked,
+
+    /// The timer reached its [`RepeatLimit`], its [`Schedule`](crate::Schedule) ended, or its handler returned
+    /// [`TickControl::Complete`].
+    Completed,
+
+    /// The timer has been closed.
+    Closed
+}
+
+/// Limit of a repeating timer, after which the timer disarms itself. See [`TimerQueue::schedule_repeating`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RepeatLimit {
+    /// The handler is called at most this number of times.
+    Times(u64),
+
+    /// The handler is not called at, or after, this time.
+    Until(Instant)
+}
+
+/// What a timer queue does when a handler panics. See [`TimerQueue::set_panic_policy`].
+///
+/// In every case the panic is caught, so it never reaches the OS timer thread or other timers of the queue. The payload
+/// of the last panic can be retrieved by [`Timer::take_panic`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Print a warning, and keep the timer running.
+    #[default]
+    LogAndContinue,
+
+    /// Print a warning, and disable the timer with [`TimerStatus::Panicked`].
+    DisableTimer,
+
+    /// Abort the process.
+    Abort
+}
+
+/// A failure of a fallible handler, reported to the error sink of the timer's queue. See [`TimerQueue::set_error_sink`].
+#[derive(Copy, Clone, Debug)]
+pub struct TimerFailure<'a> {
+    /// The error returned by the handler.
+    pub error: &'a (dyn Error + Send + Sync + 'static),
+
+    /// The tick in which the handler failed.
+    pub tick: TickInfo,
+
+    /// Number of failures in a row, including this one.
+    pub consecutive_failures: u32,
+
+    /// Whether the timer has been disabled due to this failure.
+    pub disabled: bool
+}
+
+/// A handle of a task fired by [`fire_oneshot`]. The handle can be cloned freely; it does not own the task, and dropping it
+/// does not cancel the task.
+#[derive(Clone)]
+pub struct OneshotHandle(sync::Arc<OneshotState>);
+
+struct OneshotState {
+    stage: Mutex<OneshotStage>,
+    canceller: Mutex<Option<ReaperJob>>
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum OneshotStage { Pending, Fired, Cancelled }
+
 pub const DEFAULT_ACCEPTABLE_EXECUTION_TIME: Duration = Duration::from_secs(1);
 
 //----------------------------- FUNCTIONS --------------------------------------
@@ -68,7 +185,7 @@ pub fn schedule_oneshot<'h, F>(due: Duration, hint: Option<CallbackHint>, handle
 /// * `hint`: Behavior hint of `handler`, which impacts how the task will be scheduled
 /// * `handler`: The task to be called back
 ///
-/// returns: Result<(), [`TimerError`]>
+/// returns: Result<[`OneshotHandle`], [`TimerError`]>
 ///
 /// # Examples
 ///
@@ -80,19 +197,398 @@ pub fn schedule_oneshot<'h, F>(due: Duration, hint: Option<CallbackHint>, handle
 ///
 /// let flag = Arc::new(AtomicBool::new(false));
 /// let shared_flag = flag.clone();
-/// fire_oneshot(Duration::from_millis(100), None, move || {
+/// let task = fire_oneshot(Duration::from_millis(100), None, move || {
 ///     let _ = &shared_flag.store(true, Ordering::SeqCst);
 /// }).unwrap();
 /// thread::sleep(Duration::from_millis(200));
 /// assert!(flag.load(Ordering::SeqCst));
+/// assert!(task.is_fired());
 /// ```
 #[inline]
-pub fn fire_oneshot<F>(due: Duration, hint: Option<CallbackHint>, handler: F) -> Result<()> where F: FnOnce() + Send + 'static {
+pub fn fire_oneshot<F>(due: Duration, hint: Option<CallbackHint>, handler: F) -> Result<OneshotHandle> where F: FnOnce() + Send + 'static {
     TimerQueue::default().fire_oneshot(due, hint, handler)
 }
 
 // ----------------------------------------- IMPLEMENTATIONS ------------------------------------------
-impl<'h> Drop for Timer<'h> {
+impl TimerQueue {
+    /// Schedule a timer which owns a `state`. The handler can update the state on every tick, and the final state is returned
+    /// by [`Timer::into_state`].
+    ///
+    /// The other parameters are the same as [`TimerQueue::schedule_timer`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::{thread, time::Duration};
+    /// use native_timer::TimerQueue;
+    ///
+    /// let period = Duration::from_millis(100);
+    /// let timer = TimerQueue::default().schedule_timer_with_state(period, period, None, Vec::new(),
+    ///                                                             |ticks, info| ticks.push(info.count)).unwrap();
+    /// thread::sleep(Duration::from_millis(350));
+    /// assert_eq!(timer.into_state().unwrap(), vec![1, 2, 3]);
+    /// ```
+    pub fn schedule_timer_with_state<'h, S, F>(&self, due: Duration, period: Duration, hint: Option<CallbackHint>, state: S, mut handler: F) -> Result<Timer<'h, S>>
+        where S: Send + 'h,
+              F: FnMut(&mut S, &TickInfo) + Send + 'h
+    {
+        let state = timer_state(state);
+        let shared_state = state.clone();
+        let callback = MutWrapper::new_ticked(self.0.clone(), hint, move |tick| {
+            if let Some(s) = shared_state.lock().as_mut() {
+                handler(s, tick);
+            }
+        });
+        self.schedule_wrapper(due, period, callback, state)
+    }
+
+    /// Schedule a timer whose handler decides how the timer continues, by returning a [`TickControl`]. So the handler does
+    /// not need to reach its own [`Timer`] for stopping or rescheduling.
+    ///
+    /// The other parameters are the same as [`TimerQueue::schedule_timer`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::{thread, time::Duration};
+    /// use native_timer::{TickControl, TimerQueue};
+    ///
+    /// let mut called = 0;
+    /// let period = Duration::from_millis(50);
+    /// let timer = TimerQueue::default().schedule_controlled(period, period, None, |tick| {
+    ///     called += 1;
+    ///     if tick.count == 3 { TickControl::Stop } else { TickControl::Continue }
+    /// }).unwrap();
+    /// thread::sleep(Duration::from_millis(400));
+    /// drop(timer);
+    /// assert_eq!(called, 3);
+    /// ```
+    pub fn schedule_controlled<'h, F>(&self, due: Duration, period: Duration, hint: Option<CallbackHint>, handler: F) -> Result<Timer<'h>>
+        where F: FnMut(&TickInfo) -> TickControl + Send + 'h
+    {
+        let callback = MutWrapper::new_controlled(self.0.clone(), hint, handler);
+        self.schedule_wrapper(due, period, callback, timer_state(()))
+    }
+
+    /// Schedule a fixed-delay timer: the handler is first called after `due`, then each next call happens `delay` after the
+    /// previous call *finishes*. So a slow handler never overlaps with itself, and missed ticks do not pile up.
+    ///
+    /// In contrast, [`TimerQueue::schedule_timer`] is fixed-rate, where ticks happen every period regardless of the
+    /// handler's execution time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::{thread, time::Duration};
+    /// use native_timer::{CallbackHint, TimerQueue};
+    ///
+    /// let mut called = 0;
+    /// let delay = Duration::from_millis(50);
+    /// let hint = Some(CallbackHint::SlowFunction(Duration::from_millis(500)));
+    /// let timer = TimerQueue::default().schedule_fixed_delay(delay, delay, hint, || {
+    ///     called += 1;
+    ///     thread::sleep(Duration::from_millis(100));
+    /// }).unwrap();
+    /// thread::sleep(Duration::from_millis(420));
+    /// drop(timer);
+    /// // calls start at 50ms, 200ms and 350ms, where a fixed-rate timer would try to call every 50ms.
+    /// assert_eq!(called, 3);
+    /// ```
+    pub fn schedule_fixed_delay<'h, F>(&self, due: Duration, delay: Duration, hint: Option<CallbackHint>, handler: F) -> Result<Timer<'h>>
+        where F: FnMut() + Send + 'h
+    {
+        let callback = MutWrapper::new_fixed_delay(self.0.clone(), hint, handler);
+        self.schedule_wrapper(due, delay, callback, timer_state(()))
+    }
+
+    /// Schedule a periodic timer which stops by itself at the `limit`, then its status becomes [`TimerStatus::Completed`].
+    /// The completion can be waited for with [`Timer::wait_completed`].
+    ///
+    /// The other parameters are the same as [`TimerQueue::schedule_timer`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// use native_timer::{RepeatLimit, TimerQueue};
+    ///
+    /// let mut attempts = 0;
+    /// let period = Duration::from_millis(50);
+    /// let timer = TimerQueue::default().schedule_repeating(period, period, None, RepeatLimit::Times(5), || attempts += 1).unwrap();
+    /// assert_eq!(timer.wait_completed(Some(Duration::from_secs(1))).unwrap().count, 5);
+    /// drop(timer);
+    /// assert_eq!(attempts, 5);
+    /// ```
+    pub fn schedule_repeating<'h, F>(&self, due: Duration, period: Duration, hint: Option<CallbackHint>, limit: RepeatLimit, handler: F) -> Result<Timer<'h>>
+        where F: FnMut() + Send + 'h
+    {
+        let callback = MutWrapper::new_repeating(self.0.clone(), hint, limit, handler);
+        self.schedule_wrapper(due, period, callback, timer_state(()))
+    }
+
+    /// Schedule a timer whose handler may fail. Each error is reported to the queue's error sink (see
+    /// [`TimerQueue::set_error_sink`]), and a success resets the failure count.
+    ///
+    /// With `failure_limit` of `Some(n)`, the timer is disabled after `n` consecutive failures, which can be seen from
+    /// [`Timer::status`]. A disabled timer is enabled again by [`Timer::change_period`].
+    ///
+    /// The other parameters are the same as [`TimerQueue::schedule_timer`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::{thread, time::Duration};
+    /// use native_timer::{TimerQueue, TimerStatus};
+    ///
+    /// let queue = TimerQueue::new();
+    /// queue.set_error_sink(|failure| println!("tick {} failed: {}", failure.tick.count, failure.error));
+    ///
+    /// let mut called = 0;
+    /// let period = Duration::from_millis(50);
+    /// let timer = queue.schedule_fallible(period, period, None, Some(3), || {
+    ///     called += 1;
+    ///     Err("service unavailable")
+    /// }).unwrap();
+    /// thread::sleep(Duration::from_millis(400));
+    /// assert_eq!(timer.status(), TimerStatus::Disabled { failures: 3 });
+    /// drop(timer);
+    /// assert_eq!(called, 3);
+    /// ```
+    pub fn schedule_fallible<'h, F, E>(&self, due: Duration, period: Duration, hint: Option<CallbackHint>, failure_limit: Option<u32>, mut handler: F) -> Result<Timer<'h>>
+        where F: FnMut() -> std::result::Result<(), E> + Send + 'h,
+              E: Into<Box<dyn Error + Send + Sync>>
+    {
+        let callback = MutWrapper::new_fallible(self.0.clone(), hint, failure_limit, move |_| {
+            handler().map(|_| TickControl::Continue).map_err(Into::into)
+        });
+        self.schedule_wrapper(due, period, callback, timer_state(()))
+    }
+
+    /// Create a timer which ticks once whenever it is armed by the returned [`TimerArm`]. Owners of such a timer arm it
+    /// under their own lock, so an arming from the owner and one from the handler cannot be lost to each other.
+    pub(crate) fn schedule_manual<'h, F>(&self, hint: Option<CallbackHint>, handler: F) -> Result<(Timer<'h>, TimerArm)>
+        where F: FnMut(&TickInfo) + Send + 'h
+    {
+        let callback = MutWrapper::new_manual(self.0.clone(), hint, handler);
+        let timer = self.schedule_wrapper(platform::UNARMED_DUE, Duration::ZERO, callback, timer_state(()))?;
+        let arm = timer.callback().arm();
+        Ok((timer, arm))
+    }
+
+    /// Set the callback receiving failures of fallible handlers of this queue. Without a sink, failures are printed as
+    /// warnings.
+    ///
+    /// The sink is called from the timer's thread, so it should return quickly. A panic of the sink is printed as a warning,
+    /// or aborts the process under [`PanicPolicy::Abort`].
+    ///
+    /// ```
+    /// # use std::{thread, time::Duration};
+    /// use native_timer::TimerQueue;
+    ///
+    /// let queue = TimerQueue::new();
+    /// queue.set_error_sink(|_| panic!("broken sink"));
+    ///
+    /// let period = Duration::from_millis(50);
+    /// let failing = queue.schedule_fallible(period, period, None, None, || Err("offline")).unwrap();
+    /// let mut ticks = 0;
+    /// let healthy = queue.schedule_timer(period, period, None, || ticks += 1).unwrap();
+    /// thread::sleep(Duration::from_millis(280));
+    /// drop((failing, healthy));
+    /// assert!(ticks >= 3);
+    /// ```
+    pub fn set_error_sink<F>(&self, sink: F) where F: Fn(&TimerFailure) + Send + Sync + 'static {
+        self.0.settings.set_error_sink(Some(sync::Arc::new(sink)));
+    }
+
+    /// Remove the error sink of this queue.
+    pub fn clear_error_sink(&self) {
+        self.0.settings.set_error_sink(None);
+    }
+
+    /// Set how panics of the handlers of this queue are handled. The default is [`PanicPolicy::LogAndContinue`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::{thread, time::Duration};
+    /// use native_timer::{PanicPolicy, TimerQueue, TimerStatus};
+    ///
+    /// let queue = TimerQueue::new();
+    /// queue.set_panic_policy(PanicPolicy::DisableTimer);
+    ///
+    /// let period = Duration::from_millis(50);
+    /// let timer = queue.schedule_timer(period, period, None, || panic!("broken sensor")).unwrap();
+    /// thread::sleep(Duration::from_millis(200));
+    /// assert_eq!(timer.status(), TimerStatus::Panicked);
+    ///
+    /// let payload = timer.take_panic().unwrap();
+    /// assert_eq!(payload.downcast_ref::<&str>(), Some(&"broken sensor"));
+    /// ```
+    pub fn set_panic_policy(&self, policy: PanicPolicy) {
+        self.0.settings.set_panic_policy(policy);
+    }
+}
+
+impl<'h, S> Timer<'h, S> {
+    /// Get the current status of the timer.
+    #[inline]
+    pub fn status(&self) -> TimerStatus {
+        self.callback().status()
+    }
+
+    /// Block until the timer's handler finishes its next tick, up to `timeout`, and return the tick. If the timeout
+    /// elapses first, [`TimerError::Elapsed`] is returned.
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// use native_timer::TimerQueue;
+    ///
+    /// let period = Duration::from_millis(50);
+    /// let timer = TimerQueue::default().schedule_timer(period, period, None, || {}).unwrap();
+    /// let first = timer.wait_next_tick(Some(Duration::from_secs(1))).unwrap();
+    /// let second = timer.wait_next_tick(Some(Duration::from_secs(1))).unwrap();
+    /// assert!(second.count > first.count);
+    /// ```
+    #[inline]
+    pub fn wait_next_tick(&self, timeout: Option<Duration>) -> Result<TickInfo> {
+        self.callback().wait_next_tick(timeout)
+    }
+
+    /// Block until the timer completes, up to `timeout`, and return its last tick. The tick's count is 0 if the handler was
+    /// never called. If the timeout elapses first, [`TimerError::Elapsed`] is returned. See [`TimerStatus::Completed`].
+    #[inline]
+    pub fn wait_completed(&self, timeout: Option<Duration>) -> Result<TickInfo> {
+        self.callback().wait_completed(timeout)
+    }
+
+    /// Take the payload of the last panic of the timer's handler, if any. See [`PanicPolicy`].
+    #[inline]
+    pub fn take_panic(&self) -> Option<Box<dyn Any + Send>> {
+        self.callback().take_panic()
+    }
+
+    /// Stop the timer, wait until its handler is idle, and return the timer's final state.
+    pub fn into_state(mut self) -> Result<S> {
+        self.close()?;
+        let state = self.state().lock().take();
+        state.ok_or(TimerError::SynchronizationBroken)
+    }
+}
+
+impl<S: Send + 'static> Timer<'static, S> {
+    /// Close the timer without blocking the caller. The timer stops invoking its task right away, but waiting for a running
+    /// task and releasing the closure are done by the background reaper of the timer's [`TimerQueue`].
+    ///
+    /// The returned [`Completion`] reports the result of closing, it can be waited for or `.await`ed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// use native_timer::schedule_interval;
+    ///
+    /// let timer = schedule_interval(Duration::from_millis(100), None, || println!("Tick!")).unwrap();
+    /// let closing = timer.close_async();
+    /// assert!(closing.wait().is_ok());
+    /// ```
+    pub fn close_async(self) -> Completion<()> {
+        let (source, completion) = Completion::new();
+        self.callback().mark_delete();
+
+        let core = self.callback().queue_core();
+        let mut timer = self;
+        let job = Box::new(move || source.complete(timer.close()));
+
+        if let Err(e) = core.reaper.dispatch(job) {
+            println!("WARNING: timer reaper is not available, the timer is closed in the caller thread. Error = {e:?}");
+        }
+        completion
+    }
+
+    /// Close the timer in background and forget about it. This is [`Timer::close_async`] without waiting for the result.
+    #[inline]
+    pub fn detach(self) {
+        drop(self.close_async());
+    }
+}
+
+impl OneshotHandle {
+    pub(crate) fn new() -> Self {
+        OneshotHandle(sync::Arc::new(OneshotState {
+            stage: Mutex::new(OneshotStage::Pending),
+            canceller: Mutex::new(None)
+        }))
+    }
+
+    /// Cancel the task if it has not been fired yet. Returns `true` if the task is cancelled by this call.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+    /// # use std::thread;
+    /// # use std::time::Duration;
+    /// use native_timer::fire_oneshot;
+    ///
+    /// let flag = Arc::new(AtomicBool::new(false));
+    /// let shared_flag = flag.clone();
+    /// let task = fire_oneshot(Duration::from_millis(200), None, move || shared_flag.store(true, Ordering::SeqCst)).unwrap();
+    /// assert!(task.cancel());
+    /// thread::sleep(Duration::from_millis(300));
+    /// assert!(!flag.load(Ordering::SeqCst));
+    /// assert!(!task.is_fired());
+    /// ```
+    pub fn cancel(&self) -> bool {
+        {
+            let mut stage = self.0.stage.lock();
+            if *stage != OneshotStage::Pending {
+                return false;
+            }
+            *stage = OneshotStage::Cancelled;
+        }
+        if let Some(canceller) = self.0.canceller.lock().take() {
+            canceller();
+        }
+        true
+    }
+
+    /// Check whether the task has been fired, i.e. its handler has started.
+    #[inline]
+    pub fn is_fired(&self) -> bool {
+        *self.0.stage.lock() == OneshotStage::Fired
+    }
+
+    /// Check whether the task has been cancelled.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.stage.lock() == OneshotStage::Cancelled
+    }
+
+    /// Set the job to release the task's timer when it is cancelled. The job is dropped if the task has already fired,
+    /// since the fired task releases its own timer.
+    pub(crate) fn set_canceller(&self, canceller: ReaperJob) {
+        // holding the stage lock keeps `start` from taking the canceller before it is set.
+        let stage = self.0.stage.lock();
+        if *stage == OneshotStage::Pending {
+            *self.0.canceller.lock() = Some(canceller);
+        }
+    }
+
+    /// Mark the task as fired. Returns `false` if the task has been cancelled, which also means the canceller takes care
+    /// of the timer's cleanup.
+    pub(crate) fn start(&self) -> bool {
+        let mut stage = self.0.stage.lock();
+        if *stage == OneshotStage::Cancelled {
+            return false;
+        }
+        *stage = OneshotStage::Fired;
+        drop(stage);
+        self.0.canceller.lock().take();
+        true
+    }
+}
+
+impl<'h, S> Drop for Timer<'h, S> {
     fn drop(&mut self) {
         if let Err(e) = self.close() {
             println!("WARNING: an error occurred during timer destruction. Memory might leak. Error = {e:?}");
@@ -104,7 +600,9 @@ impl Display for TimerError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             TimerError::OsError(code, msg) => write!(f, "OS error {code}: {msg}"),
-            TimerError::SynchronizationBroken => write!(f, "A sync object is broken from a thread's panic!")
+            TimerError::SynchronizationBroken => write!(f, "A sync object is broken from a thread's panic!"),
+            TimerError::Panicked(msg) => write!(f, "A task panicked: {msg}"),
+            TimerError::Elapsed => write!(f, "A task did not finish in time")
         }
     }
 }
@@ -8,34 +8,37 @@ use libc::{c_int, sigaction, sigevent, sigval, sigemptyset, siginfo_t, size_t, s
            syscall, SYS_gettid, timer_create, itimerspec, timespec, c_long, timer_settime, timer_t, timer_delete, CLOCK_REALTIME};
 use sync_wait_object::WaitEvent;
 use crate::{
-    CallbackHint, Result, TimerError,
-    common::MutWrapper
+    CallbackHint, Result, TimerError, OneshotHandle,
+    common::{MutWrapper, MutCallable, Reaper, QueueSettings, TimerState, TimerHandleUnsafeRepr, timer_state, CallbackId, invoke_callback, peek_callback}
 };
-use crate::common::MutCallable;
 
 // ------------------------------------- DATA STRUCTURE & MARKERS -------------------------------------
 pub struct TimerQueueCore {
     timer_queue: Sender<TimerCreationUnsafeRequest>,
-    quick_dispatcher: Sender<MutWrapperUnsafeRepr>
+    quick_dispatcher: Sender<CallbackId>,
+    pub(crate) reaper: Reaper,
+    pub(crate) settings: QueueSettings
 }
 
 #[doc = include_str!("../docs/TimerQueue.md")]
-pub struct TimerQueue(sync::Arc<TimerQueueCore>);
+pub struct TimerQueue(pub(crate) sync::Arc<TimerQueueCore>);
 
 #[doc = include_str!("../docs/Timer.md")]
-pub struct Timer<'h> {
+pub struct Timer<'h, S = ()> {
     handle: Option<timer_t>,
-    callback: Box<MutWrapper<'h>>
+    callback: Box<MutWrapper<'h>>,
+    state: TimerState<S>
 }
 
+// POSIX timer handle is just an identity to the kernel, and can be operated from any thread.
+unsafe impl<'h, S: Send> Send for Timer<'h, S> {}
+unsafe impl<'h, S: Send> Sync for Timer<'h, S> {}
+
 type MutWrapperUnsafeRepr = usize;
-type TimerHandleUnsafeRepr = usize;
 type TimerHandleResult = Result<TimerHandleUnsafeRepr>;
 
 struct TimerCreationUnsafeRequest {
-    due: Duration,
-    period: Duration,
-    callback_ref: MutWrapperUnsafeRepr,
+    callback_id: CallbackId,
     signal: Sender<TimerHandleResult>
 }
 
@@ -55,11 +58,7 @@ fn to_result(ret: c_int) -> Result<()> {
     else { Err(get_errno()) }
 }
 
-fn close_timer(handle: timer_t, callback: &MutWrapper) -> Result<()> {
-    let acceptable_execution_time = match callback.hint {
-        Some(CallbackHint::SlowFunction(d)) => d,
-        _ => crate::DEFAULT_ACCEPTABLE_EXECUTION_TIME
-    };
+fn close_timer(handle: timer_t, callback: &MutWrapper, acceptable_execution_time: Option<Duration>) -> Result<()> {
     callback.mark_delete();
 
     change_period(handle, Duration::ZERO, Duration::ZERO)?;
@@ -67,6 +66,13 @@ fn close_timer(handle: timer_t, callback: &MutWrapper) -> Result<()> {
     unsafe { to_result(timer_delete(handle)) }
 }
 
+fn acceptable_execution_time(hint: Option<CallbackHint>) -> Duration {
+    match hint {
+        Some(CallbackHint::SlowFunction(d)) => d,
+        _ => crate::DEFAULT_ACCEPTABLE_EXECUTION_TIME
+    }
+}
+
 fn change_period(handle: timer_t, due: Duration, period: Duration) -> Result<()> {
     let interval = itimerspec {
         it_value: to_timespec(due),
@@ -75,6 +81,22 @@ fn change_period(handle: timer_t, due: Duration, period: Duration) -> Result<()>
     unsafe { to_result(timer_settime(handle, 0, &interval, ptr::null_mut())) }
 }
 
+/// Native period of a timer which fires only once.
+pub(crate) const ONESHOT_PERIOD: Duration = Duration::ZERO;
+
+/// Due time for creating a timer which does not fire until it is armed.
+pub(crate) const UNARMED_DUE: Duration = Duration::ZERO;
+
+/// Re-arm a timer from its callback. Unlike [`change_period`], zero `due` does not disarm the timer.
+pub(crate) fn rearm_timer(_core: &TimerQueueCore, handle: TimerHandleUnsafeRepr, due: Duration, period: Duration) -> Result<()> {
+    change_period(handle as timer_t, due.max(Duration::from_nanos(1)), period)
+}
+
+#[inline]
+pub(crate) fn disarm_timer(_core: &TimerQueueCore, handle: TimerHandleUnsafeRepr) -> Result<()> {
+    change_period(handle as timer_t, Duration::ZERO, Duration::ZERO)
+}
+
 fn to_timespec(value: Duration) -> timespec {
     let ns = value.as_nanos();
     let secs = ns / 1_000_000_000;
@@ -88,11 +110,12 @@ static mut DEFAULT_QUEUE: Option<TimerQueue> = None;
 
 impl TimerQueue {
     /// Create a new TimerQueue
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         let (dispatcher, receiver) = channel::<TimerCreationUnsafeRequest>();
         thread::spawn(move || {
             for req in receiver {
-                let timer = Self::schedule_signal_callback(req.due, req.period, req.callback_ref);
+                let timer = Self::create_signal_timer(req.callback_id);
                 let message = timer.map(|t| t as TimerHandleUnsafeRepr);
                 req.signal.send(message).unwrap();
             }
@@ -100,22 +123,23 @@ impl TimerQueue {
 
         let (quick_dispatcher, quick_queue) = channel();
         thread::spawn(move || {
-            for ctx in quick_queue {
-                Self::unsafe_call(ctx);
+            for id in quick_queue {
+                invoke_callback(id);
             }
         });
         TimerQueue(sync::Arc::new(TimerQueueCore{
-            timer_queue: dispatcher, quick_dispatcher
+            timer_queue: dispatcher, quick_dispatcher, reaper: Reaper::new(), settings: QueueSettings::default()
         }))
     }
 
     /// Default OS common timer queue
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> &'static TimerQueue {
         unsafe {
             DEFAULT_QUEUE_ONCE.call_once(|| {
                 DEFAULT_QUEUE = Some(Self::new());
             });
-            DEFAULT_QUEUE.as_ref().unwrap()
+            (*ptr::addr_of!(DEFAULT_QUEUE)).as_ref().unwrap()
         }
     }
 
@@ -123,54 +147,45 @@ impl TimerQueue {
     pub fn schedule_timer<'h, F>(&self, due: Duration, period: Duration, hint: Option<CallbackHint>, handler: F) -> Result<Timer<'h>>
         where F: FnMut() + Send + 'h
     {
-        let callback = Box::new(MutWrapper::new(self.0.clone(), hint, handler));
-        let timer_unsafe = self.create_timer(due, period, &callback)?;
-
-        timer_unsafe.map(|t| Timer::<'h> {
-            handle: Some(t as timer_t),
-            callback
-        })
+        let callback = MutWrapper::new(self.0.clone(), hint, handler);
+        self.schedule_wrapper(due, period, callback, timer_state(()))
     }
 
     #[doc = include_str!("../docs/TimerQueue_schedule_oneshot.md")]
     pub fn schedule_oneshot<'h, F>(&self, due: Duration, hint: Option<CallbackHint>, handler: F) -> Result<Timer<'h>>
         where F: FnOnce() + Send + 'h
     {
-        let callback = Box::new(MutWrapper::new_once(self.0.clone(), hint, handler));
-        let timer_unsafe = self.create_timer(due, Duration::ZERO, &callback)?;
-
-        timer_unsafe.map(|t| Timer::<'h> {
-            handle: Some(t as timer_t),
-            callback
-        })
+        let callback = MutWrapper::new_once(self.0.clone(), hint, handler);
+        self.schedule_wrapper(due, Duration::ZERO, callback, timer_state(()))
     }
 
     #[doc = include_str!("../docs/TimerQueue_fire_oneshot.md")]
-    pub fn fire_oneshot<F>(&self, due: Duration, hint: Option<CallbackHint>, handler: F) -> Result<()>
+    pub fn fire_oneshot<F>(&self, due: Duration, hint: Option<CallbackHint>, handler: F) -> Result<OneshotHandle>
     where F: FnOnce() + Send + 'static
     {
+        let oneshot = OneshotHandle::new();
+        let task = oneshot.clone();
         let journal: WaitEvent<Option<(TimerHandleUnsafeRepr, MutWrapperUnsafeRepr)>> = WaitEvent::new_init(None);
         let mut journal_write = journal.clone();
+        let core = self.0.clone();
         let wrapper = move || {
+            if !task.start() { return; }
+
             handler();
             let (handle, callback_ptr) = journal.wait_reset(None, || None, |v| v.is_some()).unwrap().unwrap();
 
-            // TODO use a common thread to clean up?
-            thread::spawn(move || {
-                let callback = unsafe { Box::from_raw(callback_ptr as *mut MutWrapper) };
-                close_timer(handle as timer_t, &callback).unwrap();
-            });
+            Self::dispose_oneshot(&core, handle, callback_ptr);
         };
 
-        let callback = Box::new(MutWrapper::new_once(self.0.clone(), hint, wrapper));
-        let timer_unsafe = self.create_timer(due, Duration::ZERO, &callback)?;
+        let callback = MutWrapper::new_once(self.0.clone(), hint, wrapper);
+        let handle = self.create_timer(due, Duration::ZERO, &callback)? as TimerHandleUnsafeRepr;
 
         let callback_ptr = Box::into_raw(callback) as MutWrapperUnsafeRepr;
 
-        match timer_unsafe {
-            Ok(handle) => journal_write.set_state(Some((handle, callback_ptr))).map_err(|e| e.into()),
-            Err(e) => Err(e.into())
-        }
+        let core = self.0.clone();
+        oneshot.set_canceller(Box::new(move || Self::dispose_oneshot(&core, handle, callback_ptr)));
+        journal_write.set_state(Some((handle, callback_ptr)))?;
+        Ok(oneshot)
     }
 
     #[inline]
@@ -178,31 +193,53 @@ impl TimerQueue {
         TimerQueue(context)
     }
 
-    fn dispatch_quick_call(&self, ctx: MutWrapperUnsafeRepr) -> Result<()> {
-        self.0.quick_dispatcher.send(ctx).map_err(|_| TimerError::SynchronizationBroken)
+    pub(crate) fn schedule_wrapper<'h, S>(&self, due: Duration, period: Duration, callback: Box<MutWrapper<'h>>, state: TimerState<S>) -> Result<Timer<'h, S>> {
+        let handle = self.create_timer(due, period, &callback)?;
+        Ok(Timer::<'h, S> { handle: Some(handle), callback, state })
+    }
+
+    /// Release a one-shot timer from the queue's reaper, since the timer cannot be closed from its own callback.
+    fn dispose_oneshot(core: &TimerQueueCore, handle: TimerHandleUnsafeRepr, callback_ptr: MutWrapperUnsafeRepr) {
+        let job = Box::new(move || {
+            let callback = unsafe { Box::from_raw(callback_ptr as *mut MutWrapper) };
+            if let Err(e) = close_timer(handle as timer_t, &callback, Some(acceptable_execution_time(callback.hint))) {
+                println!("WARNING: an error occurred during one-shot timer destruction. Error = {e:?}");
+            }
+        });
+        if let Err(e) = core.reaper.dispatch(job) {
+            println!("WARNING: cannot clean up a one-shot timer. Memory might leak. Error = {e:?}");
+        }
     }
 
-    fn create_timer<'h>(&self, due: Duration, period: Duration, callback: &Box<MutWrapper<'h>>) -> Result<TimerHandleResult> {
-        let callback_ref = callback.as_ref() as *const MutWrapper as MutWrapperUnsafeRepr;
+    fn create_timer(&self, due: Duration, period: Duration, callback: &MutWrapper) -> Result<timer_t> {
         let (signal, timer_receiver) = channel();
-        let unsafe_request = TimerCreationUnsafeRequest { due, period, callback_ref, signal };
+        let unsafe_request = TimerCreationUnsafeRequest { callback_id: callback.id(), signal };
 
         self.0.timer_queue.send(unsafe_request).unwrap();
 
-        match timer_receiver.recv() {
+        let handle = match timer_receiver.recv() {
             Err(_) => Err(TimerError::SynchronizationBroken),
-            Ok(thm) => Ok(thm)
+            Ok(thm) => thm
+        }?;
+
+        // the callback must know its timer before the first tick, so the timer is armed only after this.
+        callback.attach(handle, period);
+        let timer = handle as timer_t;
+        if let Err(e) = change_period(timer, due, callback.native_period(period)) {
+            unsafe { timer_delete(timer); }
+            return Err(e);
         }
+        Ok(timer)
     }
 
-    fn schedule_signal_callback(due: Duration, period: Duration, callback_ref: MutWrapperUnsafeRepr) -> Result<timer_t>
+    fn create_signal_timer(callback_id: CallbackId) -> Result<timer_t>
     {
         unsafe {
             let mut sa_mask = mem::zeroed();
             to_result(sigemptyset(&mut sa_mask))?;
             let sa = sigaction {
                 sa_flags: libc::SA_SIGINFO,
-                sa_sigaction: Self::timer_callback as size_t,
+                sa_sigaction: Self::timer_callback as *const () as size_t,
                 sa_mask,
                 sa_restorer: None
             };
@@ -210,53 +247,64 @@ impl TimerQueue {
 
             let mut sev: sigevent = mem::zeroed();
             sev.sigev_value = sigval {
-                sival_ptr: callback_ref as *mut c_void
+                sival_ptr: callback_id as *mut c_void
             };
             sev.sigev_signo = SIGRTMIN();
             sev.sigev_notify = SIGEV_THREAD_ID;
             sev.sigev_notify_thread_id = syscall(SYS_gettid) as i32;
             let mut timer = ptr::null_mut();
             to_result(timer_create(CLOCK_REALTIME, &mut sev, &mut timer))?;
-
-            let interval = itimerspec {
-                it_value: to_timespec(due),
-                it_interval: to_timespec(period)
-            };
-
-            to_result(timer_settime(timer, 0, &interval, ptr::null_mut()))?;
             Ok(timer)
         }
     }
 
-    fn unsafe_call(ctx: MutWrapperUnsafeRepr) {
-        let wrapper = unsafe { &mut *(ctx as *mut MutWrapper) };
-        if let Err(e) = wrapper.call() {
-            println!("WARNING: Error occurred during timer callback: {e:?}");
+    extern "C" fn timer_callback(_id: c_int, signal: *mut siginfo_t, _uc: *mut c_void){
+        let id = unsafe { (*signal).si_value().sival_ptr as CallbackId };
+
+        // a signal of a destroyed timer may still arrive, in that case the ID is no longer valid.
+        let Some((hint, core)) = peek_callback(id, |w| (w.hint, w.queue_core())) else { return };
+        match hint {
+            Some(CallbackHint::SlowFunction(_)) => { thread::spawn(move || invoke_callback(id)); },
+            _ => if core.quick_dispatcher.send(id).is_err() {
+                println!("WARNING: quick dispatcher of the timer queue is not available.");
+            }
         }
     }
+}
 
-    extern "C" fn timer_callback(_id: c_int, signal: *mut siginfo_t, _uc: *mut c_void){
-        let ctx = unsafe { (*signal).si_value().sival_ptr as MutWrapperUnsafeRepr };
+impl<'h, S> Timer<'h, S> {
+    #[inline]
+    pub(crate) fn callback(&self) -> &MutWrapper<'h> {
+        &self.callback
+    }
 
-        let wrapper = unsafe { &mut *(ctx as *mut MutWrapper) };
-        match wrapper.hint {
-            Some(CallbackHint::SlowFunction(_)) => { thread::spawn(move || Self::unsafe_call(ctx)); },
-            _ => wrapper.timer_queue().dispatch_quick_call(ctx).unwrap()
-        }
+    #[inline]
+    pub(crate) fn state(&self) -> &TimerState<S> {
+        &self.state
     }
-}
 
-impl<'h> Timer<'h> {
     /// Reset the timer with a new due time and a new period.
     pub fn change_period(&self, due: Duration, period: Duration) -> Result<()> {
-        if let Some(handle) = self.handle { change_period(handle, due, period) }
+        if let Some(handle) = self.handle {
+            self.callback.reset_period(period);
+            change_period(handle, due, self.callback.native_period(period))
+        }
         else { Ok(()) }
     }
 
     /// Manually close the timer. It is safe to call this method more than once, but it is not thread-safe.
     pub fn close(&mut self) -> Result<()> {
         if let Some(handle) = self.handle.take() {
-            close_timer(handle, &self.callback)
+            close_timer(handle, &self.callback, Some(acceptable_execution_time(self.callback.hint)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Close the timer, waiting for a running callback however long it takes.
+    pub(crate) fn close_unbounded(&mut self) -> Result<()> {
+        if let Some(handle) = self.handle.take() {
+            close_timer(handle, &self.callback, None)
         } else {
             Ok(())
         }
@@ -0,0 +1,183 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Arc,
+    time::{Duration, Instant}
+};
+use parking_lot::Mutex;
+use crate::{CallbackHint, Result, Timer, TimerQueue, common::DeadlineArm};
+
+// ------------------------------------- DATA STRUCTURES & MARKERS ------------------------------------
+/// A stage of escalation, given to the handler of a [`Watchdog`] or a [`WatchdogRegistry`] when a peer has not been fed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Escalation {
+    /// Index of the stage, in the order of the stages' timeouts.
+    pub stage: usize,
+
+    /// Time since the last feed.
+    pub elapsed: Duration
+}
+
+/// Expects to be fed within a timeout, otherwise calls its handler. Several stages can be given, e.g. a warning after 5
+/// seconds and a restart after 10 seconds; each stage is reported once after each feed.
+///
+/// The watchdog starts counting when it is created.
+///
+/// # Examples
+///
+/// ```
+/// # use std::{sync::{Arc, Mutex}, thread, time::Duration};
+/// use native_timer::{TimerQueue, Watchdog};
+///
+/// let stages = Arc::new(Mutex::new(Vec::new()));
+/// let log = stages.clone();
+/// let timeouts = [Duration::from_millis(100), Duration::from_millis(200)];
+/// let watchdog = Watchdog::new(TimerQueue::default(), &timeouts, None, move |e| log.lock().unwrap().push(e.stage)).unwrap();
+///
+/// for _ in 0..3 {
+///     thread::sleep(Duration::from_millis(50));
+///     watchdog.feed().unwrap();
+/// }
+/// assert!(stages.lock().unwrap().is_empty());
+///
+/// thread::sleep(Duration::from_millis(300));
+/// assert_eq!(*stages.lock().unwrap(), [0, 1]);
+/// ```
+pub struct Watchdog<'h>(WatchdogRegistry<'h, ()>);
+
+/// Watches many named peers with a single timer. Each peer is registered by its first [`WatchdogRegistry::feed`], and
+/// escalates through the same stages as a [`Watchdog`].
+///
+/// # Examples
+///
+/// ```
+/// # use std::{sync::{Arc, Mutex}, thread, time::Duration};
+/// use native_timer::{TimerQueue, WatchdogRegistry};
+///
+/// let silent = Arc::new(Mutex::new(Vec::new()));
+/// let log = silent.clone();
+/// let registry = WatchdogRegistry::new(TimerQueue::default(), &[Duration::from_millis(100)], None, move |name: &&str, _| {
+///     log.lock().unwrap().push(*name);
+/// }).unwrap();
+///
+/// registry.feed("worker-1").unwrap();
+/// registry.feed("worker-2").unwrap();
+/// for _ in 0..4 {
+///     thread::sleep(Duration::from_millis(50));
+///     registry.feed("worker-1").unwrap();
+/// }
+/// assert_eq!(*silent.lock().unwrap(), ["worker-2"]);
+/// ```
+pub struct WatchdogRegistry<'h, K> {
+    shared: Arc<Mutex<Registry<K>>>,
+    _timer: Timer<'h>
+}
+
+struct Registry<K> {
+    /// Timeouts of the stages since the last feed, in ascending order.
+    stages: Vec<Duration>,
+    peers: HashMap<K, Peer>,
+    arm: DeadlineArm
+}
+
+struct Peer {
+    last_feed: Instant,
+    next_stage: usize
+}
+
+// --------------------------------------- IMPLEMENTATIONS --------------------------------------------
+impl<'h> Watchdog<'h> {
+    /// Create a watchdog on `queue`, which calls `handler` at each of the `stages` timeouts after the last feed.
+    pub fn new<F>(queue: &TimerQueue, stages: &[Duration], hint: Option<CallbackHint>, mut handler: F) -> Result<Self>
+        where F: FnMut(&Escalation) + Send + 'h
+    {
+        let registry = WatchdogRegistry::new(queue, stages, hint, move |_: &(), e: &Escalation| handler(e))?;
+        registry.feed(())?;
+        Ok(Watchdog(registry))
+    }
+
+    /// Reset the deadline, and restart the escalation from the first stage.
+    #[inline]
+    pub fn feed(&self) -> Result<()> {
+        self.0.feed(())
+    }
+
+    /// Get the last stage reported since the last feed.
+    #[inline]
+    pub fn stage(&self) -> Option<usize> {
+        self.0.stage(&())
+    }
+}
+
+impl<'h, K: Eq + Hash + Clone + Send + 'h> WatchdogRegistry<'h, K> {
+    /// Create a registry on `queue`, which calls `handler` with a peer's name at each of the `stages` timeouts after the
+    /// peer's last feed.
+    pub fn new<F>(queue: &TimerQueue, stages: &[Duration], hint: Option<CallbackHint>, mut handler: F) -> Result<Self>
+        where F: FnMut(&K, &Escalation) + Send + 'h
+    {
+        let mut stages = stages.to_vec();
+        stages.sort();
+        let shared = Arc::new(Mutex::new(Registry { stages, peers: HashMap::new(), arm: DeadlineArm::default() }));
+        let registry = shared.clone();
+        let (timer, arm) = queue.schedule_manual(hint, move |_| {
+            let events = registry.lock().fire(Instant::now());
+            for (name, escalation) in events {
+                handler(&name, &escalation);
+            }
+        })?;
+        shared.lock().arm.attach(arm);
+        Ok(WatchdogRegistry { shared, _timer: timer })
+    }
+
+    /// Feed the peer `name`, registering it if it is new.
+    pub fn feed(&self, name: K) -> Result<()> {
+        let now = Instant::now();
+        let mut r = self.shared.lock();
+        r.peers.insert(name, Peer { last_feed: now, next_stage: 0 });
+        match r.stages.first() {
+            Some(&first) => r.arm.arm_before(now, now + first),
+            None => Ok(())
+        }
+    }
+
+    /// Stop watching the peer `name`. Returns `false` if the peer is unknown.
+    pub fn unregister(&self, name: &K) -> bool {
+        self.shared.lock().peers.remove(name).is_some()
+    }
+
+    /// Get the last stage reported for the peer `name` since its last feed.
+    pub fn stage(&self, name: &K) -> Option<usize> {
+        self.shared.lock().peers.get(name).and_then(|p| p.next_stage.checked_sub(1))
+    }
+
+    /// Names of the peers which have reached at least one stage since their last feed.
+    pub fn overdue(&self) -> Vec<K> {
+        self.shared.lock().peers.iter().filter(|(_, p)| p.next_stage > 0).map(|(k, _)| k.clone()).collect()
+    }
+}
+
+impl<K: Clone> Registry<K> {
+    /// Handle a tick of the timer, and return the escalations which are due.
+    fn fire(&mut self, now: Instant) -> Vec<(K, Escalation)> {
+        let mut events = Vec::new();
+        let mut next: Option<Instant> = None;
+        for (name, peer) in self.peers.iter_mut() {
+            while let Some(&timeout) = self.stages.get(peer.next_stage) {
+                let due = peer.last_feed + timeout;
+                if now < due {
+                    next = Some(next.map_or(due, |n| n.min(due)));
+                    break;
+                }
+                events.push((name.clone(), Escalation { stage: peer.next_stage, elapsed: now - peer.last_feed }));
+                peer.next_stage += 1;
+            }
+        }
+        self.arm.fired();
+        if let Some(next) = next {
+            if let Err(e) = self.arm.arm_before(now, next) {
+                println!("WARNING: cannot re-arm the watchdog. Error = {e:?}");
+            }
+        }
+        events
+    }
+}
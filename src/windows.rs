@@ -8,23 +8,28 @@ use windows::Win32::{
     Foundation::{HANDLE, BOOLEAN, ERROR_IO_PENDING, WIN32_ERROR, GetLastError},
     System::Threading::*,
 };
-use super::timer::{CallbackHint, Result, DEFAULT_ACCEPTABLE_EXECUTION_TIME};
+use super::timer::{CallbackHint, Result, OneshotHandle, DEFAULT_ACCEPTABLE_EXECUTION_TIME};
 use crate::common::*;
 use super::TimerError;
 
 // ------------------ DATA STRUCTURE -------------------------------
 #[doc = include_str!("../docs/TimerQueue.md")]
-pub struct TimerQueue(sync::Arc<TimerQueueCore>);
+pub struct TimerQueue(pub(crate) sync::Arc<TimerQueueCore>);
 
 #[doc = include_str!("../docs/Timer.md")]
-pub struct Timer<'h> {
+pub struct Timer<'h, S = ()> {
     queue: sync::Arc<TimerQueueCore>,
     handle: HANDLE,
     callback: Box<MutWrapper<'h>>,
-    acceptable_execution_time: Duration
+    acceptable_execution_time: Duration,
+    state: TimerState<S>
 }
 
-pub(crate) struct TimerQueueCore(HANDLE);
+pub(crate) struct TimerQueueCore {
+    handle: HANDLE,
+    pub(crate) reaper: Reaper,
+    pub(crate) settings: QueueSettings
+}
 
 // ----------------------------------------- FUNCTIONS ------------------------------------------------
 #[inline]
@@ -46,11 +51,28 @@ fn change_period(queue: HANDLE, timer: HANDLE, due: Duration, period: Duration)
     to_result(unsafe { ChangeTimerQueueTimer(queue, timer, due.as_millis() as u32, period.as_millis() as u32).as_bool() })
 }
 
-fn close_timer(queue: HANDLE, handle: HANDLE, acceptable_execution_time: Duration, callback: &MutWrapper) -> Result<()> {
-    callback.mark_delete();
+/// Due time for a timer which should not fire, since a timer queue timer cannot be disarmed without being deleted.
+const PARKED_DUE: Duration = Duration::from_millis(u32::MAX as u64 - 1);
 
-    let key = callback as *const MutWrapper as MutWrapperUnsafeRepr;
-    remove_mutwrapper_unsafe_repr(key);
+/// Native period of a timer which fires once, but can still be re-armed from its callback. An expired timer queue timer
+/// with zero period cannot be changed anymore, so such a timer is given a period which never elapses in practice.
+pub(crate) const ONESHOT_PERIOD: Duration = PARKED_DUE;
+
+/// Due time for creating a timer which does not fire until it is armed.
+pub(crate) const UNARMED_DUE: Duration = PARKED_DUE;
+
+/// Re-arm a timer from its callback.
+pub(crate) fn rearm_timer(core: &TimerQueueCore, handle: TimerHandleUnsafeRepr, due: Duration, period: Duration) -> Result<()> {
+    change_period(core.handle, HANDLE(handle as isize), due, period)
+}
+
+#[inline]
+pub(crate) fn disarm_timer(core: &TimerQueueCore, handle: TimerHandleUnsafeRepr) -> Result<()> {
+    change_period(core.handle, HANDLE(handle as isize), PARKED_DUE, Duration::ZERO)
+}
+
+fn close_timer(queue: HANDLE, handle: HANDLE, acceptable_execution_time: Option<Duration>, callback: &MutWrapper) -> Result<()> {
+    callback.mark_delete();
 
     // ensure no callback during destruction
     change_period(queue, handle, Duration::default(), Duration::default())?;
@@ -67,6 +89,19 @@ fn close_timer(queue: HANDLE, handle: HANDLE, acceptable_execution_time: Duratio
     Ok(())
 }
 
+/// Release a one-shot timer from the queue's reaper, since the timer cannot be closed from its own callback.
+fn dispose_oneshot(core: &TimerQueueCore, queue: HANDLE, handle: HANDLE, acceptable_execution_time: Duration, callback_ptr: usize) {
+    let job = Box::new(move || {
+        let callback = unsafe { Box::from_raw(callback_ptr as *mut MutWrapper) };
+        if let Err(e) = close_timer(queue, handle, Some(acceptable_execution_time), &callback) {
+            println!("WARNING: an error occurred during one-shot timer destruction. Error = {e:?}");
+        }
+    });
+    if let Err(e) = core.reaper.dispatch(job) {
+        println!("WARNING: cannot clean up a one-shot timer. Memory might leak. Error = {e:?}");
+    }
+}
+
 static DEFAULT_QUEUE_INIT: sync::Once = sync::Once::new();
 static mut DEFAULT_QUEUE: Option<TimerQueue> = None;
 
@@ -81,61 +116,63 @@ fn get_acceptable_execution_time(hint: Option<CallbackHint>) -> Duration {
 impl TimerQueue {
     /// Default OS common timer queue
     #[inline]
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> &'static TimerQueue {
         DEFAULT_QUEUE_INIT.call_once(|| unsafe {
-            DEFAULT_QUEUE = Some(TimerQueue(sync::Arc::new(TimerQueueCore(HANDLE(0)))));
+            DEFAULT_QUEUE = Some(TimerQueue(sync::Arc::new(TimerQueueCore { handle: HANDLE(0), reaper: Reaper::new(), settings: QueueSettings::default() })));
         });
-        unsafe { DEFAULT_QUEUE.as_ref().unwrap() }
+        unsafe { (*std::ptr::addr_of!(DEFAULT_QUEUE)).as_ref().unwrap() }
     }
 
     /// Create a new TimerQueue
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         let core = unsafe {  CreateTimerQueue().unwrap() };
-        TimerQueue(sync::Arc::new(TimerQueueCore(core)))
+        TimerQueue(sync::Arc::new(TimerQueueCore { handle: core, reaper: Reaper::new(), settings: QueueSettings::default() }))
     }
 
     #[doc = include_str!("../docs/TimerQueue_schedule_timer.md")]
     pub fn schedule_timer<'h, F>(&self, due: Duration, period: Duration, hint: Option<CallbackHint>, handler: F) -> Result<Timer<'h>>
         where F: FnMut() + Send + 'h
     {
-        let acceptable_execution_time = get_acceptable_execution_time(hint);
-        let period = period.as_millis() as u32;
-        let callback = Box::new(MutWrapper::new(self.0.clone(), hint, handler));
-        let timer_handle = self.create_timer(due, period, hint, &callback)?;
-        Ok(Timer::<'h> { queue: self.0.clone(), handle: timer_handle, callback, acceptable_execution_time })
+        let callback = MutWrapper::new(self.0.clone(), hint, handler);
+        self.schedule_wrapper(due, period, callback, timer_state(()))
     }
 
     #[doc = include_str!("../docs/TimerQueue_schedule_oneshot.md")]
     pub fn schedule_oneshot<'h, F>(&self, due: Duration, hint: Option<CallbackHint>, handler: F) -> Result<Timer<'h>> where F: FnOnce() + Send + 'h {
-        let acceptable_execution_time = get_acceptable_execution_time(hint);
-        let callback = Box::new(MutWrapper::new_once(self.0.clone(), hint, handler));
-        let timer_handle = self.create_timer(due, 0, hint, &callback)?;
-        Ok(Timer::<'h> { queue: self.0.clone(), handle: timer_handle, callback, acceptable_execution_time })
+        let callback = MutWrapper::new_once(self.0.clone(), hint, handler);
+        self.schedule_wrapper(due, Duration::ZERO, callback, timer_state(()))
     }
 
     #[doc = include_str!("../docs/TimerQueue_fire_oneshot.md")]
-    pub fn fire_oneshot<F>(&self, due: Duration, hint: Option<CallbackHint>, handler: F) -> Result<()> where F: FnOnce() + Send + 'static {
+    pub fn fire_oneshot<F>(&self, due: Duration, hint: Option<CallbackHint>, handler: F) -> Result<OneshotHandle> where F: FnOnce() + Send + 'static {
+        let oneshot = OneshotHandle::new();
+        let task = oneshot.clone();
         let journal: WaitEvent<Option<(HANDLE, usize)>> = WaitEvent::new_init(None);
         let mut journal_write = journal.clone();
-        let queue_handle = self.0.0;
+        let queue_handle = self.0.handle;
 
         let acceptable_execution_time = get_acceptable_execution_time(hint);
 
+        let core = self.0.clone();
         let wrapper = move || {
+            if !task.start() { return; }
+
             handler();
             let (handle, callback_ptr) = journal.wait_reset(None, || None, |v| v.is_some()).unwrap().unwrap();
 
             // Need to exit the timer thread before cleaning up the handle.
-            // TODO use a common thread?
-            std::thread::spawn(move || {
-                let callback = unsafe { Box::from_raw(callback_ptr as *mut MutWrapper) };
-                close_timer(queue_handle, handle, acceptable_execution_time, &callback).unwrap();
-            });
+            dispose_oneshot(&core, queue_handle, handle, acceptable_execution_time, callback_ptr);
         };
-        let callback = Box::new(MutWrapper::new_once(self.0.clone(), hint, wrapper));
-        let timer_handle = self.create_timer(due, 0, hint, &callback)?;
+        let callback = MutWrapper::new_once(self.0.clone(), hint, wrapper);
+        let timer_handle = self.create_timer(due, Duration::ZERO, hint, &callback)?;
         let callback_ptr = Box::into_raw(callback) as usize;
-        journal_write.set_state(Some((timer_handle, callback_ptr))).map_err(|e| e.into())
+
+        let core = self.0.clone();
+        oneshot.set_canceller(Box::new(move || dispose_oneshot(&core, queue_handle, timer_handle, acceptable_execution_time, callback_ptr)));
+        journal_write.set_state(Some((timer_handle, callback_ptr)))?;
+        Ok(oneshot)
     }
 
     #[allow(dead_code)]
@@ -143,54 +180,69 @@ impl TimerQueue {
         TimerQueue(context)
     }
 
-    fn create_timer(&self, due: Duration, period: u32, hint: Option<CallbackHint>, callback: &Box<MutWrapper>) -> Result<HANDLE>
+    pub(crate) fn schedule_wrapper<'h, S>(&self, due: Duration, period: Duration, callback: Box<MutWrapper<'h>>, state: TimerState<S>) -> Result<Timer<'h, S>> {
+        let acceptable_execution_time = get_acceptable_execution_time(callback.hint);
+        let timer_handle = self.create_timer(due, period, callback.hint, &callback)?;
+        Ok(Timer::<'h, S> { queue: self.0.clone(), handle: timer_handle, callback, acceptable_execution_time, state })
+    }
+
+    fn create_timer(&self, due: Duration, period: Duration, hint: Option<CallbackHint>, callback: &MutWrapper) -> Result<HANDLE>
     {
+        let native_period = callback.native_period(period);
         let option = hint.map(|o| match o {
             CallbackHint::QuickFunction => WT_EXECUTEINPERSISTENTTHREAD,
             CallbackHint::SlowFunction(_) => WT_EXECUTELONGFUNCTION
         }).unwrap_or(WT_EXECUTEDEFAULT);
-        let option = if period == 0 { option | WT_EXECUTEONLYONCE } else { option };
+        let option = if native_period.is_zero() { option | WT_EXECUTEONLYONCE } else { option };
 
         let mut timer_handle = HANDLE::default();
-        let callback_ref = callback.as_ref() as *const MutWrapper as *const c_void;
-
-        save_mutwrapper_unsafe_repr(callback_ref as MutWrapperUnsafeRepr);
+        let callback_ref = callback.id() as *const c_void;
 
+        // the callback must know its timer before the first tick, so the timer is parked until it is attached.
         let create_timer_queue_timer_result = unsafe {
-            CreateTimerQueueTimer(&mut timer_handle, self.0.0, Some(timer_callback), Some(callback_ref),
-                                  due.as_millis() as u32, period, option).as_bool()
+            CreateTimerQueueTimer(&mut timer_handle, self.0.handle, Some(timer_callback), Some(callback_ref),
+                                  PARKED_DUE.as_millis() as u32, native_period.as_millis() as u32, option).as_bool()
         };
-        if create_timer_queue_timer_result {
-            Ok(timer_handle)
-        } else {
-            Err(get_last_error())
+        if !create_timer_queue_timer_result {
+            return Err(get_last_error());
+        }
+        callback.attach(timer_handle.0 as TimerHandleUnsafeRepr, period);
+        if let Err(e) = change_period(self.0.handle, timer_handle, due, native_period) {
+            unsafe { DeleteTimerQueueTimer(self.0.handle, timer_handle, None); }
+            return Err(e);
         }
+        Ok(timer_handle)
     }
 }
 
 extern "system" fn timer_callback(ctx: *mut c_void, _: BOOLEAN) {
-    let valid = is_mutwrapper_unsafe_repr_valid(ctx as MutWrapperUnsafeRepr);
-    if valid {
-        let wrapper = unsafe { &mut *(ctx as *mut MutWrapper) };
-        if let Err(e) = wrapper.call() {
-            println!("WARNING: Error occurred during timer callback: {e:?}");
-        }
-    }
+    invoke_callback(ctx as CallbackId);
 }
 
 impl Drop for TimerQueueCore {
     fn drop(&mut self) {
-        if !self.0.is_invalid() {
-            assert!(unsafe { DeleteTimerQueue(self.0).as_bool() });
-            self.0 = HANDLE::default();
+        if !self.handle.is_invalid() {
+            assert!(unsafe { DeleteTimerQueue(self.handle).as_bool() });
+            self.handle = HANDLE::default();
         }
     }
 }
 
-impl<'h> Timer<'h> {
+impl<'h, S> Timer<'h, S> {
+    #[inline]
+    pub(crate) fn callback(&self) -> &MutWrapper<'h> {
+        &self.callback
+    }
+
+    #[inline]
+    pub(crate) fn state(&self) -> &TimerState<S> {
+        &self.state
+    }
+
     /// Reset the timer with a new due time and a new period.
     pub fn change_period(&self, due: Duration, period: Duration) -> Result<()> {
-        change_period(self.queue.0, self.handle, due, period)
+        self.callback.reset_period(period);
+        change_period(self.queue.handle, self.handle, due, self.callback.native_period(period))
     }
 
     /// Manually close the timer. It is safe to call this method more than once, but it is not thread-safe.
@@ -198,7 +250,18 @@ impl<'h> Timer<'h> {
         if !self.handle.is_invalid() {
             let handle = self.handle;
             self.handle = HANDLE::default();
-            close_timer(self.queue.0, handle, self.acceptable_execution_time, &self.callback)
+            close_timer(self.queue.handle, handle, Some(self.acceptable_execution_time), &self.callback)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Close the timer, waiting for a running callback however long it takes.
+    pub(crate) fn close_unbounded(&mut self) -> Result<()> {
+        if !self.handle.is_invalid() {
+            let handle = self.handle;
+            self.handle = HANDLE::default();
+            close_timer(self.queue.handle, handle, None, &self.callback)
         } else {
             Ok(())
         }